@@ -0,0 +1,301 @@
+//! Minimal client for the Battle.net World of Warcraft community API.
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::time::Duration;
+
+use reqwest::header::{Headers, UserAgent};
+use reqwest::{self, Client, StatusCode};
+use serde::de::DeserializeOwned;
+use url::Url;
+
+use constants::USER_AGENT;
+
+/// Regions served by the Battle.net API.
+pub const REGIONS: &[&str] = &["us", "eu", "kr", "tw"];
+
+/// Errors returned by the Battle.net client.
+#[derive(Debug)]
+pub enum BattleNetError {
+    /// The requested character or guild doesn't exist.
+    NotFound,
+    /// The API key was rejected.
+    Unauthorized,
+    /// Any other unexpected HTTP status.
+    Status(StatusCode),
+    /// The request couldn't be built from the provided names.
+    InvalidRequest,
+    /// The request couldn't be sent, or no response was received.
+    Transport(reqwest::Error),
+    /// The response couldn't be parsed.
+    Parse(reqwest::Error),
+}
+
+impl fmt::Display for BattleNetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BattleNetError::NotFound => write!(f, "not found"),
+            BattleNetError::Unauthorized => write!(f, "API key rejected"),
+            BattleNetError::Status(code) => write!(f, "unexpected status {}", code),
+            BattleNetError::InvalidRequest => write!(f, "invalid request"),
+            BattleNetError::Transport(ref err) => write!(f, "transport error: {}", err),
+            BattleNetError::Parse(ref err) => write!(f, "parse error: {}", err),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CharacterProfile {
+    pub name: String,
+    pub realm: String,
+    pub class: u32,
+    pub race: u32,
+    pub level: u32,
+    pub thumbnail: Option<String>,
+    pub guild: Option<CharacterGuild>,
+    pub items: Option<CharacterItems>,
+    #[serde(default)]
+    pub talents: Vec<CharacterTalents>,
+}
+
+impl CharacterProfile {
+    /// Returns the character's active specialisation, if known.
+    pub fn active_spec(&self) -> Option<&Specialisation> {
+        self.talents
+            .iter()
+            .find(|it| it.selected)
+            .and_then(|it| it.spec.as_ref())
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CharacterGuild {
+    pub name: String,
+    pub realm: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CharacterItems {
+    pub average_item_level: u32,
+    pub average_item_level_equipped: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CharacterTalents {
+    #[serde(default)]
+    pub selected: bool,
+    pub spec: Option<Specialisation>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Specialisation {
+    pub name: String,
+    pub role: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GuildRoster {
+    pub name: String,
+    pub realm: String,
+    #[serde(default)]
+    pub members: Vec<GuildMember>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct GuildMember {
+    pub character: RosterCharacter,
+    pub rank: u32,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct RosterCharacter {
+    pub name: String,
+    pub class: u32,
+    pub race: u32,
+    pub level: u32,
+}
+
+/// Breakdown of a guild roster. Each list is sorted by descending count.
+#[derive(Debug)]
+pub struct Census {
+    pub total: usize,
+    pub classes: Vec<(&'static str, usize)>,
+    pub races: Vec<(&'static str, usize)>,
+    /// Level brackets of ten levels, keyed by the lowest level in the bracket.
+    pub levels: Vec<(u32, usize)>,
+    pub ranks: Vec<(u32, usize)>,
+}
+
+impl GuildRoster {
+    /// Breaks the roster down by class, race, level bracket and rank.
+    pub fn census(&self) -> Census {
+        let members = &self.members;
+        Census {
+            total: members.len(),
+            classes: tally(members.iter().map(|it| class_name(it.character.class))),
+            races: tally(members.iter().map(|it| race_name(it.character.race))),
+            levels: tally(members.iter().map(|it| it.character.level / 10 * 10)),
+            ranks: tally(members.iter().map(|it| it.rank)),
+        }
+    }
+}
+
+/// Counts occurrences of each key, sorted by descending count and then by key.
+fn tally<K: Hash + Eq + Ord, I: Iterator<Item = K>>(keys: I) -> Vec<(K, usize)> {
+    let mut counts = HashMap::new();
+    for key in keys {
+        *counts.entry(key).or_insert(0) += 1;
+    }
+
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    counts
+}
+
+/// Client for the Battle.net community API. Cheap to clone.
+#[derive(Clone)]
+pub struct BattleNetClient {
+    client: Client,
+    base: String,
+    token: String,
+}
+
+impl BattleNetClient {
+    /// Creates a client. `base` is the API root, and may contain a `{region}` placeholder (e.g.
+    /// `https://{region}.api.battle.net/`).
+    pub fn new(base: &str, token: &str) -> BattleNetClient {
+        let mut headers = Headers::new();
+        headers.set(UserAgent::new(USER_AGENT));
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(Some(Duration::from_secs(10)))
+            .build()
+            .expect("Reqwest client init");
+
+        BattleNetClient {
+            client,
+            base: base.into(),
+            token: token.into(),
+        }
+    }
+
+    /// Fetches a character's profile, including guild, items and talents.
+    pub fn character(&self, region: &str, realm: &str, name: &str) -> Result<CharacterProfile, BattleNetError> {
+        let url = self.endpoint(region, &["wow", "character", realm, name], "guild,items,talents")?;
+        self.get(url)
+    }
+
+    /// Fetches a guild, including its member roster.
+    pub fn guild_roster(&self, region: &str, realm: &str, name: &str) -> Result<GuildRoster, BattleNetError> {
+        let url = self.endpoint(region, &["wow", "guild", realm, name], "members")?;
+        self.get(url)
+    }
+
+    fn endpoint(&self, region: &str, segments: &[&str], fields: &str) -> Result<Url, BattleNetError> {
+        let base = self.base.replace("{region}", region);
+        let mut url = Url::parse(&base).map_err(|_| BattleNetError::InvalidRequest)?;
+
+        {
+            let mut path = url.path_segments_mut().map_err(|_| BattleNetError::InvalidRequest)?;
+            path.pop_if_empty().extend(segments);
+        }
+        url.query_pairs_mut()
+            .append_pair("fields", fields)
+            .append_pair("locale", "en_US")
+            .append_pair("apikey", &self.token);
+
+        Ok(url)
+    }
+
+    fn get<T: DeserializeOwned>(&self, url: Url) -> Result<T, BattleNetError> {
+        let mut response = self.client
+            .get(url)
+            .send()
+            .map_err(BattleNetError::Transport)?;
+
+        match response.status() {
+            StatusCode::Ok => response.json::<T>().map_err(BattleNetError::Parse),
+            StatusCode::NotFound => Err(BattleNetError::NotFound),
+            StatusCode::Unauthorized | StatusCode::Forbidden => Err(BattleNetError::Unauthorized),
+            code => Err(BattleNetError::Status(code)),
+        }
+    }
+}
+
+/// Converts a realm name to the slug used in Armory URLs.
+pub fn realm_slug(realm: &str) -> String {
+    realm
+        .to_lowercase()
+        .chars()
+        .filter(|it| *it != '\'')
+        .map(|it| if it.is_whitespace() { '-' } else { it })
+        .collect()
+}
+
+/// Builds the Armory URL for a character.
+pub fn armory_url(region: &str, realm: &str, name: &str) -> String {
+    let locale = match region {
+        "eu" => "en-gb",
+        "kr" => "ko-kr",
+        "tw" => "zh-tw",
+        _ => "en-us",
+    };
+    format!(
+        "https://worldofwarcraft.com/{}/character/{}/{}",
+        locale,
+        realm_slug(realm),
+        name.to_lowercase()
+    )
+}
+
+/// Builds the URL for a character's thumbnail, from the `thumbnail` field of its profile.
+pub fn thumbnail_url(region: &str, thumbnail: &str) -> String {
+    format!("https://render-{}.worldofwarcraft.com/character/{}", region, thumbnail)
+}
+
+/// Maps a class ID to its name.
+pub fn class_name(id: u32) -> &'static str {
+    match id {
+        1 => "Warrior",
+        2 => "Paladin",
+        3 => "Hunter",
+        4 => "Rogue",
+        5 => "Priest",
+        6 => "Death Knight",
+        7 => "Shaman",
+        8 => "Mage",
+        9 => "Warlock",
+        10 => "Monk",
+        11 => "Druid",
+        12 => "Demon Hunter",
+        _ => "Unknown",
+    }
+}
+
+/// Maps a race ID to its name.
+pub fn race_name(id: u32) -> &'static str {
+    match id {
+        1 => "Human",
+        2 => "Orc",
+        3 => "Dwarf",
+        4 => "Night Elf",
+        5 => "Undead",
+        6 => "Tauren",
+        7 => "Gnome",
+        8 => "Troll",
+        9 => "Goblin",
+        10 => "Blood Elf",
+        11 => "Draenei",
+        22 => "Worgen",
+        24 | 25 | 26 => "Pandaren",
+        27 => "Nightborne",
+        28 => "Highmountain Tauren",
+        29 => "Void Elf",
+        30 => "Lightforged Draenei",
+        34 => "Dark Iron Dwarf",
+        36 => "Mag'har Orc",
+        _ => "Unknown",
+    }
+}
@@ -0,0 +1,261 @@
+//! Supervisor for Drakonid. Restarts the bot if it crashes, and handles `!update` by running an update step, swapping in
+//! the new binary and rolling back if it fails to start.
+extern crate chrono;
+#[macro_use]
+extern crate clap;
+extern crate fern;
+#[macro_use]
+extern crate log;
+
+use clap::{App, Arg};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, ExitStatus};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Exit code used by `!update`. The bot exits with -100, which unix truncates to an unsigned byte.
+#[cfg(unix)]
+const UPDATE_EXIT_CODE: i32 = 156;
+#[cfg(not(unix))]
+const UPDATE_EXIT_CODE: i32 = -100;
+
+/// A freshly updated bot which exits abnormally within this many seconds is considered broken and rolled back.
+const STARTUP_GRACE_SECS: u64 = 60;
+
+/// A bot which ran for at least this many seconds before crashing resets the crash backoff.
+const STABLE_SECS: u64 = 10 * 60;
+
+const MIN_BACKOFF_SECS: u64 = 5;
+const MAX_BACKOFF_SECS: u64 = 5 * 60;
+
+/// How a single run of the bot ended.
+enum Outcome {
+    /// The bot was stopped with `!stop`.
+    Stopped,
+    /// The bot asked to be updated with `!update`.
+    Update,
+    /// The bot crashed or couldn't be started.
+    Failed,
+}
+
+fn outcome(status: &ExitStatus) -> Outcome {
+    match status.code() {
+        Some(0) => Outcome::Stopped,
+        Some(UPDATE_EXIT_CODE) => Outcome::Update,
+        _ => Outcome::Failed,
+    }
+}
+
+struct Wrapper {
+    bot: PathBuf,
+    staged: PathBuf,
+    backup: PathBuf,
+    update_cmd: Option<String>,
+    bot_args: Vec<String>,
+}
+
+impl Wrapper {
+    fn run_bot(&self) -> Outcome {
+        info!("Starting {}", self.bot.display());
+        match Command::new(&self.bot).arg("--is_wrapped").args(&self.bot_args).status() {
+            Ok(status) => {
+                info!("Bot exited with {}", status);
+                outcome(&status)
+            }
+            Err(err) => {
+                error!("Unable to start {}: {}", self.bot.display(), err);
+                Outcome::Failed
+            }
+        }
+    }
+
+    /// Runs the update command, then swaps in the staged binary if there is one. Returns true if the binary changed.
+    fn update(&self) -> bool {
+        if let Some(ref cmd) = self.update_cmd {
+            info!("Running update command: {}", cmd);
+            match shell(cmd).status() {
+                Ok(ref status) if status.success() => {}
+                Ok(status) => {
+                    error!("Update command failed with {}; keeping the current binary.", status);
+                    return false;
+                }
+                Err(err) => {
+                    error!("Unable to run update command: {}; keeping the current binary.", err);
+                    return false;
+                }
+            }
+        }
+
+        if !self.staged.exists() {
+            warn!("No new binary at {}; restarting the current one.", self.staged.display());
+            return false;
+        }
+
+        // The backup is a link or copy, leaving the current binary in place until the staged one replaces it with a
+        // single rename. Renames replace their target atomically, so the bot path always points at a complete binary.
+        let _ = fs::remove_file(&self.backup);
+        let backed_up = fs::hard_link(&self.bot, &self.backup)
+            .or_else(|_| fs::copy(&self.bot, &self.backup).map(|_| ()));
+        if let Err(err) = backed_up {
+            error!("Unable to back up {}: {}; keeping the current binary.", self.bot.display(), err);
+            return false;
+        }
+        if let Err(err) = fs::rename(&self.staged, &self.bot) {
+            error!("Unable to swap in {}: {}; keeping the current binary.", self.staged.display(), err);
+            return false;
+        }
+
+        info!("Swapped in new binary from {}", self.staged.display());
+        true
+    }
+
+    /// Restores the binary backed up by the last update, replacing the current one in a single rename.
+    fn rollback(&self) {
+        match fs::rename(&self.backup, &self.bot) {
+            Ok(()) => warn!("Rolled back to previous binary."),
+            Err(err) => error!("Unable to restore {}: {}", self.backup.display(), err),
+        }
+    }
+
+    fn supervise(&self) {
+        let mut backoff = MIN_BACKOFF_SECS;
+        let mut on_probation = false;
+
+        loop {
+            let started = Instant::now();
+            let outcome = self.run_bot();
+            let ran = started.elapsed();
+
+            match outcome {
+                Outcome::Stopped => {
+                    info!("Bot stopped; exiting.");
+                    return;
+                }
+                Outcome::Update => {
+                    on_probation = self.update();
+                    backoff = MIN_BACKOFF_SECS;
+                }
+                Outcome::Failed if on_probation && ran < Duration::from_secs(STARTUP_GRACE_SECS) => {
+                    error!("Updated bot failed within {}s of starting.", STARTUP_GRACE_SECS);
+                    self.rollback();
+                    on_probation = false;
+                }
+                Outcome::Failed => {
+                    on_probation = false;
+                    if ran >= Duration::from_secs(STABLE_SECS) {
+                        backoff = MIN_BACKOFF_SECS;
+                    }
+
+                    warn!("Bot crashed; restarting in {} seconds.", backoff);
+                    thread::sleep(Duration::from_secs(backoff));
+                    backoff = (backoff * 2).min(MAX_BACKOFF_SECS);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn shell(cmd: &str) -> Command {
+    let mut command = Command::new("sh");
+    command.arg("-c").arg(cmd);
+    command
+}
+
+#[cfg(not(unix))]
+fn shell(cmd: &str) -> Command {
+    let mut command = Command::new("cmd");
+    command.arg("/C").arg(cmd);
+    command
+}
+
+/// Appends a suffix to a path's file name, e.g. `drakonid` to `drakonid.new`.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(suffix);
+    path.with_file_name(name)
+}
+
+fn main() {
+    let matches = App::new("Drakonid Wrapper")
+        .version(crate_version!())
+        .author("Robert T. <arkan@drakon.io>")
+        .about("Runs Drakonid, restarting it on crashes and handling `!update`.")
+        .arg(
+            Arg::with_name("bot")
+                .long("bot")
+                .value_name("FILE")
+                .help("The Drakonid binary to run. Defaults to `drakonid` next to this wrapper.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("staged")
+                .long("staged")
+                .value_name("FILE")
+                .help("Where the update command leaves the new binary. Defaults to the bot binary plus `.new`.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("update_cmd")
+                .long("update-cmd")
+                .value_name("COMMAND")
+                .help("Shell command run on `!update`, which should build or fetch the new binary.")
+                .env("DRAKONID_UPDATE_CMD")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("bot_args")
+                .help("Arguments passed through to the bot. Put them after `--`.")
+                .multiple(true)
+                .last(true),
+        )
+        .get_matches();
+
+    if let Err(err) = setup_logger() {
+        panic!("Error setting up logger: {}", err);
+    }
+
+    let bot = match matches.value_of("bot") {
+        Some(path) => PathBuf::from(path),
+        None => env::current_exe()
+            .expect("Unable to locate wrapper binary.")
+            .with_file_name(format!("drakonid{}", env::consts::EXE_SUFFIX)),
+    };
+    let staged = matches
+        .value_of("staged")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| with_suffix(&bot, ".new"));
+    let backup = with_suffix(&bot, ".old");
+
+    let wrapper = Wrapper {
+        bot,
+        staged,
+        backup,
+        update_cmd: matches.value_of("update_cmd").map(String::from),
+        bot_args: matches
+            .values_of("bot_args")
+            .map(|it| it.map(String::from).collect())
+            .unwrap_or_default(),
+    };
+
+    wrapper.supervise();
+    process::exit(0);
+}
+
+fn setup_logger() -> Result<(), fern::InitError> {
+    fern::Dispatch::new()
+        .format(|out, message, record| {
+            out.finish(format_args!(
+                "[{}][wrapper][{}] {}",
+                chrono::Utc::now().format("%Y/%m/%d %H:%M:%S%.3f%z"),
+                record.level(),
+                message
+            ))
+        })
+        .level(log::LevelFilter::Info)
+        .chain(std::io::stdout())
+        .apply()?;
+    Ok(())
+}
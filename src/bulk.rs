@@ -0,0 +1,88 @@
+//! Reading and writing the files used to shorten many URLs at once.
+
+/// A URL to shorten, read from one line of a bulk file.
+#[derive(Debug, PartialEq)]
+pub struct BulkEntry {
+    /// Line number in the file, starting at 1.
+    pub line: usize,
+    pub url: String,
+    pub code: Option<String>,
+}
+
+/// Parses a bulk file. Each line holds a URL and optionally a code, separated by a comma, i.e. a CSV file with a
+/// `url,code` header or no header at all. Blank lines and lines starting with `#` are skipped, and any fields after
+/// the code are ignored.
+pub fn parse(input: &str) -> Vec<BulkEntry> {
+    let mut entries = Vec::new();
+    let mut first = true;
+
+    for (idx, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let mut fields = split_fields(line).into_iter();
+        let url = fields.next().unwrap_or_default();
+        let code = fields.next().filter(|it| !it.is_empty());
+        if first {
+            first = false;
+            if url.eq_ignore_ascii_case("url") {
+                continue;
+            }
+        }
+
+        entries.push(BulkEntry {
+            line: idx + 1,
+            url,
+            code,
+        });
+    }
+
+    entries
+}
+
+/// Formats a row of a CSV file, quoting fields where needed.
+pub fn csv_row(fields: &[&str]) -> String {
+    let mut row = fields
+        .iter()
+        .map(|field| {
+            if field.contains(|c| c == ',' || c == '"' || c == '\n' || c == '\r') {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(",");
+    row.push_str("\r\n");
+    row
+}
+
+/// Splits a line of a CSV file into trimmed fields. Quoted fields may contain commas, and `""` within them stands for
+/// a single quote.
+fn split_fields(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(field.split_off(0).trim().to_string()),
+            _ => field.push(ch),
+        }
+    }
+    fields.push(field.trim().to_string());
+
+    fields
+}
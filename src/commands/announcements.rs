@@ -0,0 +1,483 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serenity::framework::standard::{Args, Command, CommandError, CommandOptions};
+use serenity::model::channel::Message;
+use serenity::model::event::PresenceUpdateEvent;
+use serenity::model::gateway::GameType;
+use serenity::model::guild::Member;
+use serenity::model::id::{ChannelId, GuildId, RoleId, UserId};
+use serenity::prelude::{Context, Mentionable, Mutex};
+use serenity::utils::parse_role;
+
+use constants::{COLOUR_PRIMARY, COLOUR_STREAM, EMBED_FIELD_LIMIT};
+use server::config::ConfigStore;
+use types::GuildConfigMarker;
+use utils::{error_embed, require_guild, truncate, usage_error_embed};
+
+/// Name of the announcements section in each guild's configuration.
+const SECTION: &str = "announcements";
+
+/// Placeholder help shared by the commands which take a template.
+const TEMPLATE_HELP: &str = "Placeholders: `{mention}` (member mention), `{name}` (member name), `{guild}` (server \
+                             name), `{count}` (member count).";
+
+/// Minimum time between stream announcements for the same member, so a flapping presence doesn't spam the channel.
+const STREAM_COOLDOWN_SECS: u64 = 30 * 60;
+
+lazy_static! {
+    // Streaming state per guild member, used to only announce when a member starts streaming.
+    static ref STREAM_STATES: Mutex<HashMap<(GuildId, UserId), StreamState>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Default)]
+struct StreamState {
+    streaming: bool,
+    last_announced: Option<Instant>,
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct AnnouncementConfig {
+    #[serde(default)]
+    join: Option<JoinAnnouncement>,
+    #[serde(default)]
+    stream: Option<StreamAnnouncement>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct JoinAnnouncement {
+    channel: u64,
+    template: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+struct StreamAnnouncement {
+    channel: u64,
+    /// If set, only members with this role are announced.
+    role: Option<u64>,
+}
+
+/// Fills in a join template's placeholders.
+fn render_template(template: &str, mention: &str, name: &str, guild: &str, count: u64) -> String {
+    template
+        .replace("{mention}", mention)
+        .replace("{name}", name)
+        .replace("{guild}", guild)
+        .replace("{count}", &count.to_string())
+}
+
+/// Returns the guild's name and member count from the cache, if available.
+fn guild_details(guild_id: GuildId) -> (String, u64) {
+    match guild_id.find() {
+        Some(guild) => {
+            let guild = guild.read();
+            (guild.name.clone(), guild.member_count)
+        }
+        None => ("this server".into(), 0),
+    }
+}
+
+/// Posts the guild's join announcement (if configured) for a newly joined member. Called from the event handler.
+pub fn member_joined(store: &ConfigStore, guild_id: GuildId, member: &Member) {
+    let conf: AnnouncementConfig = store.get_section(guild_id, SECTION);
+    let join = match conf.join {
+        Some(join) => join,
+        None => return,
+    };
+
+    let (guild_name, count) = guild_details(guild_id);
+    let (mention, name) = {
+        let user = member.user.read();
+        (user.mention(), user.name.clone())
+    };
+
+    let text = render_template(&join.template, &mention, &name, &guild_name, count);
+    if let Err(err) = ChannelId(join.channel).say(text) {
+        warn!("Unable to post join announcement in guild {}: {:?}", guild_id, err);
+    }
+}
+
+/// Records a member's streaming state, returning true if they've just started streaming and haven't been announced
+/// recently. Members who stop streaming are forgotten once their cooldown has passed, so only members who could be
+/// announced are tracked.
+fn should_announce_stream(guild_id: GuildId, user_id: UserId, streaming: bool) -> bool {
+    let mut states = STREAM_STATES.lock();
+    let cooldown = Duration::from_secs(STREAM_COOLDOWN_SECS);
+
+    if !streaming {
+        let expired = match states.get_mut(&(guild_id, user_id)) {
+            Some(state) => {
+                state.streaming = false;
+                state.last_announced.map_or(true, |at| at.elapsed() >= cooldown)
+            }
+            None => false,
+        };
+        if expired {
+            states.remove(&(guild_id, user_id));
+        }
+        return false;
+    }
+
+    let state = states.entry((guild_id, user_id)).or_insert_with(StreamState::default);
+    let started = !state.streaming;
+    state.streaming = true;
+    if !started {
+        return false;
+    }
+
+    match state.last_announced {
+        Some(at) if at.elapsed() < cooldown => false,
+        _ => {
+            state.last_announced = Some(Instant::now());
+            true
+        }
+    }
+}
+
+/// Returns true if the member in a presence update has the given role.
+fn has_role(event: &PresenceUpdateEvent, guild_id: GuildId, role: RoleId) -> bool {
+    if let Some(ref roles) = event.roles {
+        return roles.contains(&role);
+    }
+
+    match guild_id.member(event.presence.user_id) {
+        Ok(member) => member.roles.contains(&role),
+        Err(err) => {
+            warn!("Unable to fetch member {} of guild {}: {:?}", event.presence.user_id, guild_id, err);
+            false
+        }
+    }
+}
+
+/// Posts the guild's stream announcement (if configured) when a member starts streaming. Called from the event handler.
+pub fn presence_updated(store: &ConfigStore, event: &PresenceUpdateEvent) {
+    let guild_id = match event.guild_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    let conf: AnnouncementConfig = store.get_section(guild_id, SECTION);
+    let stream = match conf.stream {
+        Some(stream) => stream,
+        None => return,
+    };
+
+    let stream_game = event.presence
+        .game
+        .as_ref()
+        .filter(|game| game.kind == GameType::Streaming);
+    let game = match stream_game {
+        Some(game) => game,
+        None => {
+            should_announce_stream(guild_id, event.presence.user_id, false);
+            return;
+        }
+    };
+
+    if let Some(role) = stream.role {
+        if !has_role(event, guild_id, RoleId(role)) {
+            return;
+        }
+    }
+    if !should_announce_stream(guild_id, event.presence.user_id, true) {
+        return;
+    }
+
+    let user_id = event.presence.user_id;
+    let name = match event.presence.user {
+        Some(ref user) => user.read().name.clone(),
+        None => user_id.get().map(|it| it.name).unwrap_or_else(|_| "Someone".into()),
+    };
+
+    let res = ChannelId(stream.channel).send_message(|m| {
+        m.content(user_id.mention()).embed(|mut e| {
+            e = e.title(format!("{} is now live!", name))
+                .colour(*COLOUR_STREAM)
+                .description(&game.name);
+
+            if let Some(ref url) = game.url {
+                e = e.url(url).field("Watch", url, false);
+            }
+
+            e
+        })
+    });
+
+    if let Err(err) = res {
+        warn!("Unable to post stream announcement in guild {}: {:?}", guild_id, err);
+    }
+}
+
+/// Configures the join announcement for the current guild.
+pub struct JoinSet {
+    opts: Arc<CommandOptions>,
+}
+
+impl JoinSet {
+    pub fn new() -> JoinSet {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(format!(
+            "Set the channel and message used to announce new members. {}",
+            TEMPLATE_HELP
+        ));
+        opts.usage = Some("#CHANNEL MESSAGE".into());
+        opts.example = Some("#general Welcome to {guild}, {mention}! You're member number {count}.".into());
+        opts.guild_only = true;
+        opts.min_args = Some(2);
+
+        JoinSet {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for JoinSet {
+    fn execute(&self, ctx: &mut Context, msg: &Message, mut args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let channel = match args.single::<ChannelId>() {
+            Ok(channel) => channel,
+            Err(_) => {
+                usage_error_embed("ann join set", "You must mention a channel.", Arc::clone(&self.opts), msg);
+                return Ok(());
+            }
+        };
+
+        let template = args.rest().trim().to_string();
+        if template.is_empty() {
+            usage_error_embed("ann join set", "No message specified.", Arc::clone(&self.opts), msg);
+            return Ok(());
+        }
+
+        let (guild_name, count) = guild_details(guild_id);
+        let preview = render_template(&template, &msg.author.mention(), &msg.author.name, &guild_name, count);
+
+        let store = guild_conf!(ctx.data);
+        let res = store.update_section(guild_id, SECTION, |conf: &mut AnnouncementConfig| {
+            conf.join = Some(JoinAnnouncement {
+                channel: channel.0,
+                template,
+            });
+        });
+
+        if let Err(err) = res {
+            error!("Unable to save join announcement for guild {}: {}", guild_id, err);
+            error_embed(&msg.channel_id, "Unable to save announcement. Ask your admin for assistance.", None, |e| e);
+            return Ok(());
+        }
+
+        let _ = msg.channel_id.send_message(|m| {
+            m.embed(|e| {
+                e.title("Join Announcement Set")
+                    .colour(*COLOUR_PRIMARY)
+                    .field("Channel", channel.mention(), true)
+                    .field("Preview", truncate(&preview, EMBED_FIELD_LIMIT), false)
+            })
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Removes the join announcement for the current guild.
+pub struct JoinDel {
+    opts: Arc<CommandOptions>,
+}
+
+impl JoinDel {
+    pub fn new() -> JoinDel {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Stop announcing new members.".into());
+        opts.guild_only = true;
+        opts.max_args = Some(0);
+
+        JoinDel {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for JoinDel {
+    fn execute(&self, ctx: &mut Context, msg: &Message, _: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let store = guild_conf!(ctx.data);
+        let res = store.update_section(guild_id, SECTION, |conf: &mut AnnouncementConfig| conf.join.take());
+
+        match res {
+            Ok(Some(_)) => {
+                let _ = msg.channel_id.send_message(|m| {
+                    m.embed(|e| {
+                        e.title("Join Announcement Removed")
+                            .colour(*COLOUR_PRIMARY)
+                            .description("New members will no longer be announced.")
+                    })
+                });
+            }
+            Ok(None) => {
+                error_embed(&msg.channel_id, "No join announcement is set.", None, |e| e);
+            }
+            Err(err) => {
+                error!("Unable to save join announcement for guild {}: {}", guild_id, err);
+                error_embed(&msg.channel_id, "Unable to save announcement. Ask your admin for assistance.", None, |e| e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Configures the stream announcement for the current guild.
+pub struct StreamSet {
+    opts: Arc<CommandOptions>,
+}
+
+impl StreamSet {
+    pub fn new() -> StreamSet {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(
+            "Set the channel used to announce members who start streaming, optionally only for members with a role."
+                .into(),
+        );
+        opts.usage = Some("#CHANNEL [@ROLE]".into());
+        opts.example = Some("#streams @Streamers".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+        opts.max_args = Some(2);
+
+        StreamSet {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for StreamSet {
+    fn execute(&self, ctx: &mut Context, msg: &Message, mut args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let channel = match args.single::<ChannelId>() {
+            Ok(channel) => channel,
+            Err(_) => {
+                usage_error_embed("ann stream set", "You must mention a channel.", Arc::clone(&self.opts), msg);
+                return Ok(());
+            }
+        };
+
+        let role = match args.single::<String>() {
+            Ok(arg) => match parse_role(&arg) {
+                Some(id) => Some(RoleId(id)),
+                None => {
+                    usage_error_embed("ann stream set", "Invalid role mention.", Arc::clone(&self.opts), msg);
+                    return Ok(());
+                }
+            },
+            Err(_) => None,
+        };
+
+        let store = guild_conf!(ctx.data);
+        let res = store.update_section(guild_id, SECTION, |conf: &mut AnnouncementConfig| {
+            conf.stream = Some(StreamAnnouncement {
+                channel: channel.0,
+                role: role.map(|it| it.0),
+            });
+        });
+
+        if let Err(err) = res {
+            error!("Unable to save stream announcement for guild {}: {}", guild_id, err);
+            error_embed(&msg.channel_id, "Unable to save announcement. Ask your admin for assistance.", None, |e| e);
+            return Ok(());
+        }
+
+        let _ = msg.channel_id.send_message(|m| {
+            m.embed(|e| {
+                e.title("Stream Announcement Set")
+                    .colour(*COLOUR_PRIMARY)
+                    .field("Channel", channel.mention(), true)
+                    .field(
+                        "Role",
+                        role.map(|it| it.mention()).unwrap_or_else(|| "Everyone".into()),
+                        true,
+                    )
+            })
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Removes the stream announcement for the current guild.
+pub struct StreamDel {
+    opts: Arc<CommandOptions>,
+}
+
+impl StreamDel {
+    pub fn new() -> StreamDel {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Stop announcing members who start streaming.".into());
+        opts.guild_only = true;
+        opts.max_args = Some(0);
+
+        StreamDel {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for StreamDel {
+    fn execute(&self, ctx: &mut Context, msg: &Message, _: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let store = guild_conf!(ctx.data);
+        let res = store.update_section(guild_id, SECTION, |conf: &mut AnnouncementConfig| conf.stream.take());
+
+        match res {
+            Ok(Some(_)) => {
+                let _ = msg.channel_id.send_message(|m| {
+                    m.embed(|e| {
+                        e.title("Stream Announcement Removed")
+                            .colour(*COLOUR_PRIMARY)
+                            .description("Streams will no longer be announced.")
+                    })
+                });
+            }
+            Ok(None) => {
+                error_embed(&msg.channel_id, "No stream announcement is set.", None, |e| e);
+            }
+            Err(err) => {
+                error!("Unable to save stream announcement for guild {}: {}", guild_id, err);
+                error_embed(&msg.channel_id, "Unable to save announcement. Ask your admin for assistance.", None, |e| e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
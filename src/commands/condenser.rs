@@ -1,197 +1,474 @@
+use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Duration;
 
-use chrono::offset::FixedOffset;
-use chrono::DateTime;
-use reqwest::header::{Headers, UserAgent};
-use reqwest::{self, Client, StatusCode};
+use regex::Regex;
+use serenity::builder::CreateEmbed;
 use serenity::framework::standard::{Args, Command, CommandError, CommandOptions};
 use serenity::model::channel::Message;
-use serenity::model::id::ChannelId;
+use serenity::model::id::{ChannelId, GuildId, UserId};
+use serenity::model::user::User;
 use serenity::prelude::{Context, Mentionable, Mutex};
+use serenity::utils::parse_username;
 use typemap::ShareMap;
 use url::Url;
 
+use bulk::{self, BulkEntry};
+use condenser::{resolve_server, CircuitBreaker, CondenserClient, CondenserError, ServerConf};
 use constants::*;
-use types::ConfigMarker;
-use utils::{error_embed, usage_error_embed};
+use link_policy::{normalise_domain, LinkPolicy, PolicyViolation};
+use server::permissions::PermLevel;
+use server::svar::{
+    SVar, SVarCondenserAllowIpHosts, SVarCondenserAllowShortLinks, SVarCondenserMaxUrlLength, SVarCondenserQr,
+};
+use types::{ConfigMarker, GuildConfigMarker, PermissionsMarker};
+use utils::{error_embed, qr_png, require_guild, truncate, usage_error_embed};
 use workers::run_on_worker;
 
-thread_local! {
-    // Per-thread instance Reqwest's client.
-    static REQWEST_CLIENT: Client = {
-        let mut headers = Headers::new();
-        headers.set(UserAgent::new(USER_AGENT));
-        Client::builder()
-            .default_headers(headers)
-            .timeout(Some(Duration::from_secs(10)))
-            .build()
-            .expect("Reqwest client init")
-    };
+/// Name of the Condenser section in each guild's configuration.
+const SECTION: &str = "condenser";
+
+/// Name of the section holding each guild's allowed and blocked domains.
+const POLICY_SECTION: &str = "link_policy";
+
+/// Number of codes shown per page of `!condenser list`.
+const LIST_PAGE_SIZE: usize = 10;
+
+/// File name for QR codes attached by `!shorten`.
+const QR_FILE_NAME: &str = "qr.png";
+
+/// File name for the results of a bulk `!shorten`.
+const BULK_RESULTS_FILE_NAME: &str = "shortened.csv";
+
+/// Largest file accepted by a bulk `!shorten`, in bytes.
+const MAX_BULK_FILE_SIZE: u64 = 64 * 1024;
+
+/// Most URLs accepted by a bulk `!shorten`.
+const MAX_BULK_ENTRIES: usize = 100;
+
+/// Longest destination shown per code in `!condenser list`, keeping a full page within Discord's embed limits.
+const LIST_URL_LENGTH: usize = 200;
+
+/// Number of days of hits shown in `!condenser meta`.
+const SPARKLINE_DAYS: usize = 14;
+
+/// Separates the submitter record from the note added by `!condenser edit`.
+const NOTE_SEPARATOR: &str = " | Note: ";
+
+lazy_static! {
+    // Submitter and guild IDs in the metadata attached by `!shorten`, e.g.
+    // `<1234@5678> Submitted via Drakonid by Foo#1234 (via Bar)`. There's no guild ID for codes submitted in PMs. The
+    // IDs come first, as the names after them are chosen by users and may contain anything.
+    static ref SUBMITTER_REGEX: Regex = Regex::new(r"^<(\d+)(?:@(\d+))?> Submitted via Drakonid by ").unwrap();
+    // Circuit breakers per server, shared by every command talking to it.
+    static ref BREAKERS: Mutex<HashMap<Url, Arc<CircuitBreaker>>> = Mutex::new(HashMap::new());
 }
 
-header!{ (XApiKey, "X-API-Key") => [String] }
+/// Name of the server a message was sent in, for recording where codes were submitted.
+fn server_name(msg: &Message) -> String {
+    match msg.guild() {
+        Some(guild) => guild.read().name.clone(),
+        None => "PM".into(),
+    }
+}
 
-#[derive(Serialize, Debug)]
-struct ShortenRequest {
-    url: String,
-    code: Option<String>,
-    meta: Option<String>,
+/// Builds the metadata attached to codes created through the bot, recording who submitted them and where.
+fn submitter_meta(msg: &Message) -> String {
+    let ids = match msg.guild_id() {
+        Some(guild_id) => format!("{}@{}", msg.author.id, guild_id),
+        None => msg.author.id.to_string(),
+    };
+    format!("<{}> Submitted via Drakonid by {} (via {})", ids, msg.author.tag(), server_name(msg))
 }
 
-#[derive(Deserialize, Debug)]
-struct ShortenResponse {
-    #[serde(with = "url_serde")]
-    short_url: Url,
+/// Builds the metadata for an edited code. The original submitter is kept so ownership doesn't change hands; codes
+/// without a submitter record are attributed to the editor, whose own submitter metadata is `editor_meta`.
+fn edited_meta(user_meta: Option<&str>, editor_meta: &str, note: Option<&str>) -> String {
+    let mut meta = match user_meta {
+        Some(meta) if SUBMITTER_REGEX.is_match(meta) => {
+            meta.split(NOTE_SEPARATOR).next().unwrap_or(meta).to_string()
+        }
+        _ => editor_meta.to_string(),
+    };
+
+    if let Some(note) = note {
+        meta.push_str(NOTE_SEPARATOR);
+        meta.push_str(note);
+    }
+    meta
 }
 
-#[derive(Serialize, Debug)]
-struct DeleteRequest {
-    code: String,
+/// Checks that a URL can be shortened, reporting a usage error if not.
+fn check_scheme(url: &Url, cmd: &str, opts: &Arc<CommandOptions>, msg: &Message) -> bool {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        usage_error_embed(
+            cmd,
+            &format!("Invalid URL scheme: {}", url.scheme()),
+            Arc::clone(opts),
+            msg,
+        );
+        return false;
+    }
+    true
 }
 
-#[derive(Deserialize, Debug)]
-struct DeleteResponse {
-    code: String,
-    status: String,
+/// Checks whether a code's metadata records it as submitted by the given user. Codes without a submitter ID belong
+/// to nobody, as names can be changed or copied.
+fn submitted_by(user_meta: Option<&str>, user: &User) -> bool {
+    user_meta
+        .and_then(|meta| SUBMITTER_REGEX.captures(meta))
+        .map_or(false, |caps| caps[1].parse::<u64>().ok() == Some(user.id.0))
 }
 
-#[derive(Deserialize, Debug)]
-struct MetaResponse {
-    #[serde(with = "url_serde")]
-    full_url: Url,
-    meta: LinkMetadata,
+/// Checks whether a code's metadata records it as submitted in the given guild.
+fn submitted_in(user_meta: Option<&str>, guild_id: GuildId) -> bool {
+    user_meta
+        .and_then(|meta| SUBMITTER_REGEX.captures(meta))
+        .and_then(|caps| caps.get(2).and_then(|it| it.as_str().parse::<u64>().ok()))
+        == Some(guild_id.0)
 }
 
-#[derive(Deserialize, Debug)]
-struct LinkMetadata {
-    owner: String,
-    time: DateTime<FixedOffset>,
-    user_meta: Option<String>,
+/// Who may manage a code, worked out before handing off to a worker.
+struct ManageRights {
+    /// Whether every code may be managed.
+    any: bool,
+    /// A guild whose codes may be managed, as the author is a superuser there.
+    guild: Option<GuildId>,
 }
 
-fn handle_response_err(err: reqwest::Error, channel_id: ChannelId, usr_mention: &str) {
-    match err.status() {
-        Some(code) => {
-            warn!(
-                "Invalid response but with a HTTP status code? {} -> {:?}",
-                code, err
-            );
-            error_embed(
-                &channel_id,
-                "A HTTP error occured when communicating with Condenser. Ask your admin for assistance.",
-                Some(&usr_mention),
-                |e| e
-            );
-        }
-        None => {
-            warn!("Error sending Condenser shorten request: {:?}", err);
-            error_embed(
-                &channel_id,
-                "An error occurred when communicating with Condenser. Ask your admin for assistance.",
-                Some(&usr_mention),
-                |e| e
-            );
+impl ManageRights {
+    /// Bot owners may manage every code, as may superusers whose guild has its own Condenser server. On the shared
+    /// server, superusers may manage codes submitted in their guild.
+    fn of(ctx: &Context, msg: &Message) -> ManageRights {
+        let level = perms!(ctx.data).level(msg.author.id, msg.guild_id());
+        let guild = msg.guild_id().filter(|_| level >= PermLevel::Superuser);
+        let own_server = guild.map_or(false, |guild| {
+            guild_conf!(ctx.data).get_section::<GuildCondenser>(guild, SECTION).server.is_some()
+        });
+
+        ManageRights {
+            any: level >= PermLevel::Owner || own_server,
+            guild,
         }
     }
+
+    /// Checks whether a code with the given metadata may be managed by `user`.
+    fn allows(&self, user_meta: Option<&str>, user: &User) -> bool {
+        self.any || submitted_by(user_meta, user) || self.guild.map_or(false, |it| submitted_in(user_meta, it))
+    }
 }
 
-fn handle_response_code(code: StatusCode, channel_id: ChannelId, usr_mention: &str) {
-    match code {
-        StatusCode::Unauthorized => {
-            error_embed(
-                &channel_id,
-                "The bot's API key is invalid. Ask your admin for assistance.",
-                Some(&usr_mention),
-                |e| e,
-            );
+/// A guild's own Condenser server, overriding the global one.
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct GuildCondenser {
+    #[serde(default)]
+    server: Option<String>,
+    #[serde(default)]
+    key: Option<String>,
+}
+
+/// A guild's allowed and blocked domains. The rest of the link policy lives in SVars.
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct DomainLists {
+    #[serde(default)]
+    allowed: Vec<String>,
+    #[serde(default)]
+    blocked: Vec<String>,
+}
+
+/// Returns the circuit breaker for a server, creating one which DMs the bot owners when it trips if needed.
+fn breaker_for(client_data: &Arc<Mutex<ShareMap>>, server: &Url) -> Arc<CircuitBreaker> {
+    let mut breakers = BREAKERS.lock();
+    if let Some(breaker) = breakers.get(server) {
+        return Arc::clone(breaker);
+    }
+
+    let owners = perms!(client_data).owners().clone();
+    let srv = server.to_string();
+    let breaker = Arc::new(CircuitBreaker::default().on_trip(move |err| {
+        let text = format!(
+            "Condenser at {} is failing ({}). Requests will be refused until it recovers.",
+            srv, err
+        );
+        for owner in &owners {
+            match owner.create_dm_channel() {
+                Ok(channel) => {
+                    let _ = channel.say(&text);
+                }
+                Err(err) => warn!("Unable to DM owner {}: {:?}", owner, err),
+            }
         }
-        code => {
-            warn!("Unhandled status code: {}", code);
-            error_embed(
-                &channel_id,
-                "An unknown error occurred when communicating with Condenser. Ask your admin for assistance.",
-                Some(&usr_mention),
-                |e| e
-            );
+    }));
+    breakers.insert(server.clone(), Arc::clone(&breaker));
+    breaker
+}
+
+/// Builds a Condenser client for a guild, using the guild's own server if it has one and the global configuration
+/// otherwise. If `need_key` is set, an API key must be configured.
+fn client_for(client_data: &Arc<Mutex<ShareMap>>, guild: Option<GuildId>, need_key: bool) -> Option<CondenserClient> {
+    let local: GuildCondenser = match guild {
+        Some(guild) => guild_conf!(client_data).get_section(guild, SECTION),
+        None => GuildCondenser::default(),
+    };
+
+    let (global_server, global_key) = {
+        let conf = conf!(client_data);
+        (conf.get_str(CONF_CONDENSER_SRV).ok(), conf.get_str(CONF_CONDENSER_KEY).ok())
+    };
+
+    let (server, key) = resolve_server(
+        ServerConf {
+            server: local.server.as_ref().map(|it| it.as_str()),
+            key: local.key.as_ref().map(|it| it.as_str()),
+        },
+        ServerConf {
+            server: global_server.as_ref().map(|it| it.as_str()),
+            key: global_key.as_ref().map(|it| it.as_str()),
+        },
+        need_key,
+    )?;
+    let breaker = breaker_for(client_data, &server);
+    Some(CondenserClient::new(server, key).with_breaker(breaker))
+}
+
+/// Builds a Condenser client for the guild a message was sent in, reporting an error if there's no usable server.
+fn require_client(ctx: &Context, msg: &Message, need_key: bool) -> Option<CondenserClient> {
+    let client = client_for(&ctx.data, msg.guild_id(), need_key);
+    if client.is_none() {
+        error_embed(
+            &msg.channel_id,
+            "Condenser isn't configured here. Ask your admin for assistance.",
+            None,
+            |e| e,
+        );
+    }
+    client
+}
+
+/// Builds the link policy for a guild. Links to the Condenser server itself are treated as short links.
+fn policy_for(client_data: &Arc<Mutex<ShareMap>>, guild: Option<GuildId>, client: &CondenserClient) -> LinkPolicy {
+    let shorteners = client.server().host_str().map(|it| vec![it.to_lowercase()]).unwrap_or_default();
+    let guild = match guild {
+        Some(guild) => guild,
+        None => {
+            return LinkPolicy {
+                allowed: Vec::new(),
+                blocked: Vec::new(),
+                allow_ip_hosts: SVarCondenserAllowIpHosts::get_default(),
+                allow_short_links: SVarCondenserAllowShortLinks::get_default(),
+                shorteners,
+                max_length: SVarCondenserMaxUrlLength::get_default() as usize,
+            }
         }
+    };
+
+    let store = guild_conf!(client_data);
+    let lists: DomainLists = store.get_section(guild, POLICY_SECTION);
+    LinkPolicy {
+        allowed: lists.allowed,
+        blocked: lists.blocked,
+        allow_ip_hosts: store.get::<SVarCondenserAllowIpHosts>(guild),
+        allow_short_links: store.get::<SVarCondenserAllowShortLinks>(guild),
+        shorteners,
+        max_length: store.get::<SVarCondenserMaxUrlLength>(guild) as usize,
     }
 }
 
-fn handle_parse_err(err: reqwest::Error, channel_id: ChannelId, usr_mention: &str) {
-    warn!("Error parsing response: {:?}", err);
+/// Reports a URL which breaks the link policy.
+fn handle_policy_violation(violation: PolicyViolation, channel_id: ChannelId, usr_mention: &str, url: &str) {
     error_embed(
         &channel_id,
-        "Unable to parse response from server.",
-        Some(&usr_mention),
-        |e| e,
+        "That URL isn't allowed here.",
+        Some(usr_mention),
+        |e| e.field("Reason", violation, false).field("URL", url, false),
     );
 }
 
-fn url_append(
-    base: Url,
-    append: &str,
-    channel_id: &ChannelId,
-    usr_mention: &str,
-) -> Result<Url, ()> {
-    match base.join(append) {
-        Ok(url) => Ok(url),
-        Err(err) => {
-            warn!("URL construction error: {}", err);
-            error_embed(channel_id, "Invalid code.", Some(usr_mention), |e| e);
-            Err(())
+/// Describes a Condenser error for users, logging any details they don't need.
+fn condenser_err_text(err: CondenserError) -> &'static str {
+    match err {
+        CondenserError::NotFound => "Code does not exist.",
+        CondenserError::Conflict => "The provided code already exists.",
+        CondenserError::Unauthorized => "The bot's API key is invalid. Ask your admin for assistance.",
+        CondenserError::InvalidRequest => "Invalid code.",
+        CondenserError::Parse(err) => {
+            warn!("Error parsing response: {:?}", err);
+            "Unable to parse response from server."
+        }
+        CondenserError::Transport(err) => {
+            warn!("Error sending Condenser request: {:?}", err);
+            "An error occurred when communicating with Condenser. Ask your admin for assistance."
         }
+        CondenserError::Status(code) => {
+            warn!("Unhandled status code: {}", code);
+            "An unknown error occurred when communicating with Condenser. Ask your admin for assistance."
+        }
+        CondenserError::Unavailable => "Condenser is unavailable right now. The bot owners have been told; try \
+                                        again later.",
     }
 }
 
+/// Reports a Condenser error to the user. `code` is the code the request was about, if any.
+fn handle_condenser_err(err: CondenserError, channel_id: ChannelId, usr_mention: &str, code: Option<&str>) {
+    let text = condenser_err_text(err);
+    error_embed(&channel_id, text, Some(usr_mention), |mut e| {
+        if let Some(code) = code {
+            e = e.field("Code", code, false);
+        }
+        e
+    });
+}
+
 /// Serenity command for shortening URLs with Condenser.
 pub struct CondenserShorten {
     opts: Arc<CommandOptions>,
-    key: String,
-    server: Url,
 }
 
 impl CondenserShorten {
-    pub fn new(client_data: &Arc<Mutex<ShareMap>>) -> Option<CondenserShorten> {
-        // Get configuration out of client data.
-        let conf_res = || -> Result<_, ()> {
-            let conf = conf!(client_data);
-            let key = conf.get_str(CONF_CONDENSER_KEY).map_err(|_| ())?;
-            let srv = conf.get_str(CONF_CONDENSER_SRV).map_err(|_| ())?;
-            Ok((key, Url::parse(&srv).map_err(|_| ())?))
-        };
+    pub fn new() -> CondenserShorten {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(format!(
+            "Shorten a URL with this server's Condenser service. Add `--qr` to get a QR code for the short URL. To \
+             shorten up to {} URLs at once, attach a text or CSV file with a URL and optional code on each line \
+             instead.",
+            MAX_BULK_ENTRIES
+        ));
+        opts.usage = Some("[--qr] [CODE] URL".into());
+        opts.example = Some("google https://google.com/".into());
+        opts.max_args = Some(3);
 
-        if let Ok((key, server)) = conf_res() {
-            let mut opts = CommandOptions::default();
-            opts.desc = Some(format!(
-                "Shorten a URL with the Condenser service at {}",
-                server
-            ));
-            opts.usage = Some("[CODE] URL".into());
-            opts.example = Some("google https://google.com/".into());
-            opts.min_args = Some(1);
-            opts.max_args = Some(2);
-
-            Some(CondenserShorten {
-                opts: Arc::new(opts),
-                key,
-                server,
-            })
-        } else {
-            None
+        CondenserShorten {
+            opts: Arc::new(opts),
         }
     }
+
+    /// Shortens every URL in the file attached to a message, replying with a file of results. The URLs are shortened
+    /// one at a time in a single worker job, so a large file can't tie up the whole pool.
+    fn shorten_file(&self, ctx: &Context, msg: &Message) {
+        let attachment = msg.attachments[0].clone();
+        if attachment.size > MAX_BULK_FILE_SIZE {
+            error_embed(
+                &msg.channel_id,
+                &format!("Files can be at most {} KiB.", MAX_BULK_FILE_SIZE / 1024),
+                None,
+                |e| e,
+            );
+            return;
+        }
+
+        let client = match require_client(ctx, msg, true) {
+            Some(client) => client,
+            None => return,
+        };
+
+        // Gather everything the closure will need here.
+        let usr_mention = msg.author.mention();
+        let channel_id = msg.channel_id;
+        let policy = policy_for(&ctx.data, msg.guild_id(), &client);
+        let meta = submitter_meta(msg);
+
+        run_on_worker(move || {
+            let entries = match attachment.download() {
+                Ok(data) => bulk::parse(&String::from_utf8_lossy(&data)),
+                Err(err) => {
+                    warn!("Unable to download {}: {:?}", attachment.url, err);
+                    error_embed(&channel_id, "Unable to download the attached file.", Some(&usr_mention), |e| e);
+                    return;
+                }
+            };
+
+            if entries.is_empty() {
+                error_embed(&channel_id, "The attached file doesn't contain any URLs.", Some(&usr_mention), |e| e);
+                return;
+            }
+            if entries.len() > MAX_BULK_ENTRIES {
+                error_embed(
+                    &channel_id,
+                    &format!("Files can contain at most {} URLs.", MAX_BULK_ENTRIES),
+                    Some(&usr_mention),
+                    |e| e.field("URLs", entries.len(), true),
+                );
+                return;
+            }
+
+            let total = entries.len();
+            let mut shortened = 0;
+            let mut csv = bulk::csv_row(&["line", "url", "code", "short_url", "error"]);
+            for entry in entries {
+                let (short_url, error) = match shorten_entry(&entry, &client, &policy, &meta) {
+                    Ok(short_url) => (short_url, String::new()),
+                    Err(error) => (String::new(), error),
+                };
+                if error.is_empty() {
+                    shortened += 1;
+                }
+
+                csv.push_str(&bulk::csv_row(&[
+                    &entry.line.to_string(),
+                    &entry.url,
+                    entry.code.as_ref().map(|it| it.as_str()).unwrap_or(""),
+                    &short_url,
+                    &error,
+                ]));
+            }
+
+            let res = channel_id.send_files(vec![(csv.as_bytes(), BULK_RESULTS_FILE_NAME)], |m| {
+                m.content(usr_mention).embed(|e| {
+                    e.title("URLs Shortened")
+                        .colour(*COLOUR_CONDENSER)
+                        .description(format!("Shortened {} of {} URLs. The results are attached.", shortened, total))
+                })
+            });
+            if let Err(err) = res {
+                warn!("Unable to send bulk shortening results: {:?}", err);
+            }
+        });
+    }
+}
+
+/// Shortens one URL from a bulk file, returning the short URL or the reason it wasn't shortened.
+fn shorten_entry(
+    entry: &BulkEntry,
+    client: &CondenserClient,
+    policy: &LinkPolicy,
+    meta: &str,
+) -> Result<String, String> {
+    let url = Url::parse(&entry.url).map_err(|_| "Unable to parse URL.".to_string())?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!("Invalid URL scheme: {}", url.scheme()));
+    }
+    policy.check_redirects(&url).map_err(|it| it.to_string())?;
+
+    let code = entry.code.as_ref().map(|it| it.to_uppercase());
+    client
+        .shorten(url.as_str(), code.as_ref().map(|it| it.as_str()), Some(meta))
+        .map(|it| it.into_string())
+        .map_err(|err| condenser_err_text(err).to_string())
 }
 
 impl Command for CondenserShorten {
     fn execute(
         &self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         msg: &Message,
-        mut args: Args,
+        args: Args,
     ) -> Result<(), CommandError> {
-        let argc = args.len();
-        if argc < 1 || argc > 2 {
+        // `--qr` may appear anywhere. The remaining arguments are the URL and, optionally, a code.
+        let mut qr = false;
+        let mut rest = Vec::new();
+        for arg in args.full().split_whitespace() {
+            if arg == "--qr" {
+                qr = true;
+            } else {
+                rest.push(arg);
+            }
+        }
+
+        if rest.is_empty() && !msg.attachments.is_empty() {
+            self.shorten_file(ctx, msg);
+            return Ok(());
+        }
+
+        if rest.is_empty() || rest.len() > 2 {
             usage_error_embed(
                 "shorten",
                 "Wrong number of arguments (must be 1 or 2)",
@@ -204,8 +481,8 @@ impl Command for CondenserShorten {
         let url: Url;
         let mut code: Option<String> = None;
 
-        if argc == 1 {
-            url = match args.single::<Url>() {
+        if rest.len() == 1 {
+            url = match Url::parse(rest[0]) {
                 Err(_) => {
                     usage_error_embed(
                         "shorten",
@@ -218,8 +495,8 @@ impl Command for CondenserShorten {
                 Ok(url) => url,
             };
         } else {
-            url = match args.find::<Url>() {
-                Err(_) => {
+            let pos = match rest.iter().position(|it| Url::parse(it).is_ok()) {
+                None => {
                     usage_error_embed(
                         "shorten",
                         "Unable to find a valid URL.",
@@ -228,100 +505,67 @@ impl Command for CondenserShorten {
                     );
                     return Ok(());
                 }
-                Ok(url) => url,
+                Some(pos) => pos,
             };
-            code = Some(args.single::<String>().unwrap().to_uppercase()); // We know a string will always be available.
+            url = Url::parse(rest.remove(pos)).unwrap(); // Checked above.
+            code = Some(rest[0].to_uppercase());
         }
 
-        if url.scheme() != "http" && url.scheme() != "https" {
-            usage_error_embed(
-                "shorten",
-                &format!("Invalid URL scheme: {}", url.scheme()),
-                Arc::clone(&self.opts),
-                msg,
-            );
+        if !check_scheme(&url, "shorten", &self.opts, msg) {
             return Ok(());
         }
 
-        let srv_name = if let Some(guild) = msg.guild() {
-            guild.read().name.clone()
-        } else {
-            "PM".into()
-        };
+        if let Some(guild_id) = msg.guild_id() {
+            qr = qr || guild_conf!(ctx.data).get::<SVarCondenserQr>(guild_id);
+        }
 
-        let request = ShortenRequest {
-            url: url.into_string(),
-            code,
-            meta: Some(format!(
-                "Submitted via Drakonid by {} (via {})",
-                msg.author.tag(),
-                srv_name
-            )),
+        let client = match require_client(ctx, msg, true) {
+            Some(client) => client,
+            None => return Ok(()),
         };
 
         // Gather everything the closure will need here.
         let usr_mention = msg.author.mention();
         let channel_id = msg.channel_id;
-        let api_key = self.key.clone();
-        let mut server_url = self.server.clone();
-        server_url.set_path("/api/shorten");
+        let policy = policy_for(&ctx.data, msg.guild_id(), &client);
+        let meta = submitter_meta(msg);
 
         // Hand off to the worker thread pool.
         run_on_worker(move || {
-            //let client = Client::new();
-            let response_result = REQWEST_CLIENT.with(|client| {
-                client
-                    .post(server_url)
-                    .header(XApiKey(api_key))
-                    .json(&request)
-                    .send()
-            });
+            // Following redirects makes requests, so this is only done on the worker.
+            if let Err(violation) = policy.check_redirects(&url) {
+                handle_policy_violation(violation, channel_id, &usr_mention, url.as_str());
+                return;
+            }
 
-            let mut response = match response_result {
-                Ok(res) => res,
-                Err(it) => {
-                    handle_response_err(it, channel_id, &usr_mention);
+            let url = url.into_string();
+            let short_url = match client.shorten(&url, code.as_ref().map(|it| it.as_str()), Some(&meta)) {
+                Ok(short_url) => short_url,
+                Err(err) => {
+                    handle_condenser_err(err, channel_id, &usr_mention, code.as_ref().map(|it| it.as_str()));
                     return;
                 }
             };
 
-            let parsed_response: Url = match response.status() {
-                StatusCode::Ok => match response.json::<ShortenResponse>() {
-                    Ok(res) => res.short_url,
-                    Err(err) => {
-                        handle_parse_err(err, channel_id, &usr_mention);
-                        return;
-                    }
-                },
-                StatusCode::Conflict => {
-                    // Handle Conflict here as it only occurs for shorten
-                    error_embed(
-                        &channel_id,
-                        "The provided code already exists.",
-                        Some(&usr_mention),
-                        |mut e| {
-                            if let Some(code) = request.code {
-                                e = e.field("Conflicting Code", code, true);
-                            }
-                            e
-                        },
-                    );
-                    return;
-                }
-                code => {
-                    handle_response_code(code, channel_id, &usr_mention);
-                    return;
-                }
+            let short_url = short_url.into_string();
+            let png = if qr { qr_png(&short_url) } else { None };
+            let embed = |e: CreateEmbed| {
+                e.title("URL Shortened")
+                    .colour(*COLOUR_CONDENSER)
+                    .field("Short URL", &short_url, false)
+                    .field("Original URL", &url, false)
             };
 
-            let _ = channel_id.send_message(|m| {
-                m.content(usr_mention).embed(|e| {
-                    e.title("URL Shortened")
-                        .colour(*COLOUR_CONDENSER)
-                        .field("Short URL", parsed_response.into_string(), false)
-                        .field("Original URL", request.url, false)
-                })
-            });
+            let res = match png {
+                Some(png) => channel_id.send_files(vec![(&png[..], QR_FILE_NAME)], |m| {
+                    m.content(&usr_mention)
+                        .embed(|e| embed(e).image(format!("attachment://{}", QR_FILE_NAME)))
+                }),
+                None => channel_id.send_message(|m| m.content(&usr_mention).embed(embed)),
+            };
+            if let Err(err) = res {
+                warn!("Unable to send shortened URL: {:?}", err);
+            }
         });
 
         Ok(())
@@ -334,35 +578,19 @@ impl Command for CondenserShorten {
 
 pub struct CondenserMeta {
     opts: Arc<CommandOptions>,
-    server: Url,
 }
 
 impl CondenserMeta {
-    pub fn new(client_data: &Arc<Mutex<ShareMap>>) -> Option<CondenserMeta> {
-        // Get configuration out of client data.
-        let conf_res = || -> Result<_, ()> {
-            let conf = conf!(client_data);
-            let srv = conf.get_str(CONF_CONDENSER_SRV).map_err(|_| ())?;
-            Ok(Url::parse(&srv).map_err(|_| ())?)
-        };
+    pub fn new() -> CondenserMeta {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Fetch metadata for a shortcode on this server's Condenser service.".into());
+        opts.usage = Some("CODE".into());
+        opts.example = Some("google".into());
+        opts.min_args = Some(1);
+        opts.max_args = Some(1);
 
-        if let Ok(server) = conf_res() {
-            let mut opts = CommandOptions::default();
-            opts.desc = Some(format!(
-                "Fetch metadata for a shortcode on the Condenser service at {}",
-                server
-            ));
-            opts.usage = Some("CODE".into());
-            opts.example = Some("google".into());
-            opts.min_args = Some(1);
-            opts.max_args = Some(1);
-
-            Some(CondenserMeta {
-                opts: Arc::new(opts),
-                server,
-            })
-        } else {
-            None
+        CondenserMeta {
+            opts: Arc::new(opts),
         }
     }
 }
@@ -370,7 +598,7 @@ impl CondenserMeta {
 impl Command for CondenserMeta {
     fn execute(
         &self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         msg: &Message,
         mut args: Args,
     ) -> Result<(), CommandError> {
@@ -387,74 +615,53 @@ impl Command for CondenserMeta {
             }
         };
 
+        let client = match require_client(ctx, msg, false) {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+
         // Gather everything the closure will need here.
         let usr_mention = msg.author.mention();
         let channel_id = msg.channel_id;
-        let mut server_base = self.server.clone();
-        let mut server_url = server_base.clone();
-        server_url.set_path("/api/meta/");
-        server_url = match url_append(server_url, &code, &channel_id, &usr_mention) {
-            Ok(url) => url,
-            Err(_) => {
-                return Ok(());
-            }
-        };
 
         run_on_worker(move || {
-            let response_result = REQWEST_CLIENT.with(|client| client.get(server_url).send());
-
-            let mut response = match response_result {
-                Ok(res) => res,
-                Err(it) => {
-                    handle_response_err(it, channel_id, &usr_mention);
-                    return;
-                }
-            };
-
-            let parsed_response: MetaResponse = match response.status() {
-                StatusCode::Ok => match response.json::<MetaResponse>() {
-                    Ok(res) => res,
-                    Err(err) => {
-                        handle_parse_err(err, channel_id, &usr_mention);
-                        return;
-                    }
-                },
-                StatusCode::NotFound => {
-                    error_embed(
-                        &channel_id,
-                        "Code does not exist.",
-                        Some(&usr_mention),
-                        |e| e.field("Code", code, false),
-                    );
-                    return;
-                }
-                code => {
-                    handle_response_code(code, channel_id, &usr_mention);
+            let res = client.meta(&code).and_then(|meta| Ok((client.short_url(&code)?, meta)));
+            let (short_url, meta) = match res {
+                Ok(found) => found,
+                Err(err) => {
+                    handle_condenser_err(err, channel_id, &usr_mention, Some(&code));
                     return;
                 }
             };
 
-            server_base.set_path(&code);
-
             let _ = channel_id.send_message(|m| {
                 m.content(usr_mention).embed(|mut e| {
                     e = e.title(format!("Metadata for code '{}'", code))
                         .colour(*COLOUR_CONDENSER)
-                        .field("Short URL", server_base.into_string(), false)
-                        .field("Full URL", parsed_response.full_url, false)
-                        .field("Owner", parsed_response.meta.owner, true)
+                        .field("Short URL", short_url.into_string(), false)
+                        .field("Full URL", truncate(meta.full_url.as_str(), EMBED_FIELD_LIMIT), false)
+                        .field("Owner", meta.meta.owner, true)
                         .field(
                             "Created At",
-                            parsed_response
-                                .meta
-                                .time
-                                .format("%d/%m/%Y at %H:%M:%S (%Z)"),
+                            meta.meta.time.format("%d/%m/%Y at %H:%M:%S (%Z)"),
                             true,
                         );
 
-                    if let Some(meta) = parsed_response.meta.user_meta {
-                        if meta != "" {
-                            e = e.field("User Metadata", meta, true);
+                    if let Some(user_meta) = meta.meta.user_meta {
+                        if user_meta != "" {
+                            e = e.field("User Metadata", user_meta, true);
+                        }
+                    }
+
+                    // Older servers don't track clicks, and may only report some of the statistics.
+                    if let Some(stats) = meta.stats {
+                        e = e.field("Hits", stats.hits, true);
+                        if let Some(last) = stats.last_accessed {
+                            e = e.field("Last Accessed", last.format("%d/%m/%Y at %H:%M:%S (%Z)"), true);
+                        }
+                        if let Some(spark) = stats.sparkline(SPARKLINE_DAYS) {
+                            let days = stats.daily_hits.len().min(SPARKLINE_DAYS);
+                            e = e.field(format!("Daily Hits (last {} days)", days), spark, false);
                         }
                     }
 
@@ -473,38 +680,24 @@ impl Command for CondenserMeta {
 
 pub struct CondenserDelete {
     opts: Arc<CommandOptions>,
-    key: String,
-    server: Url,
 }
 
 impl CondenserDelete {
-    pub fn new(client_data: &Arc<Mutex<ShareMap>>) -> Option<CondenserDelete> {
-        // Get configuration out of client data.
-        let conf_res = || -> Result<_, ()> {
-            let conf = conf!(client_data);
-            let key = conf.get_str(CONF_CONDENSER_KEY).map_err(|_| ())?;
-            let srv = conf.get_str(CONF_CONDENSER_SRV).map_err(|_| ())?;
-            Ok((key, Url::parse(&srv).map_err(|_| ())?))
-        };
+    pub fn new() -> CondenserDelete {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(
+            "Delete a shortcode on this server's Condenser service. You can only delete codes you submitted, \
+             unless you're a bot owner or a superuser on the server the code was submitted in. Superusers on a server \
+             with its own Condenser service may delete any code."
+                .into(),
+        );
+        opts.usage = Some("CODE".into());
+        opts.example = Some("google".into());
+        opts.min_args = Some(1);
+        opts.max_args = Some(1);
 
-        if let Ok((key, server)) = conf_res() {
-            let mut opts = CommandOptions::default();
-            opts.desc = Some(format!(
-                "Delete a shortcode on the Condenser service at {}",
-                server
-            ));
-            opts.usage = Some("CODE".into());
-            opts.example = Some("google".into());
-            opts.min_args = Some(1);
-            opts.max_args = Some(1);
-
-            Some(CondenserDelete {
-                opts: Arc::new(opts),
-                key,
-                server,
-            })
-        } else {
-            None
+        CondenserDelete {
+            opts: Arc::new(opts),
         }
     }
 }
@@ -512,7 +705,7 @@ impl CondenserDelete {
 impl Command for CondenserDelete {
     fn execute(
         &self,
-        _ctx: &mut Context,
+        ctx: &mut Context,
         msg: &Message,
         mut args: Args,
     ) -> Result<(), CommandError> {
@@ -529,61 +722,331 @@ impl Command for CondenserDelete {
             }
         };
 
-        let request = DeleteRequest { code: code.clone() };
+        let rights = ManageRights::of(ctx, msg);
+
+        let client = match require_client(ctx, msg, true) {
+            Some(client) => client,
+            None => return Ok(()),
+        };
 
         // Gather everything the closure will need here.
         let usr_mention = msg.author.mention();
+        let author = msg.author.clone();
         let channel_id = msg.channel_id;
-        let api_key = self.key.clone();
-        let mut server_url = self.server.clone();
-        server_url.set_path("/api/delete");
 
         run_on_worker(move || {
-            let response_result = REQWEST_CLIENT.with(|client| {
-                client
-                    .post(server_url)
-                    .header(XApiKey(api_key))
-                    .json(&request)
-                    .send()
-            });
-
-            let mut response = match response_result {
-                Ok(res) => res,
-                Err(it) => {
-                    handle_response_err(it, channel_id, &usr_mention);
-                    return;
-                }
-            };
-
-            let parsed_response: DeleteResponse = match response.status() {
-                StatusCode::Ok => match response.json::<DeleteResponse>() {
-                    Ok(res) => res,
+            if !rights.any {
+                let meta = match client.meta(&code) {
+                    Ok(meta) => meta,
                     Err(err) => {
-                        handle_parse_err(err, channel_id, &usr_mention);
+                        handle_condenser_err(err, channel_id, &usr_mention, Some(&code));
                         return;
                     }
-                },
-                code => {
-                    handle_response_code(code, channel_id, &usr_mention);
+                };
+                if !rights.allows(meta.meta.user_meta.as_ref().map(|it| it.as_str()), &author) {
+                    error_embed(
+                        &channel_id,
+                        "You can only delete codes you submitted.",
+                        Some(&usr_mention),
+                        |e| e.field("Code", code, false),
+                    );
                     return;
                 }
-            };
+            }
+
+            if let Err(err) = client.delete(&code) {
+                handle_condenser_err(err, channel_id, &usr_mention, Some(&code));
+                return;
+            }
+
+            let _ = channel_id.send_message(|m| {
+                m.content(usr_mention).embed(|e| {
+                    e.title("Code Deleted")
+                        .colour(*COLOUR_CONDENSER)
+                        .field("Code", code, false)
+                })
+            });
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+pub struct CondenserEdit {
+    opts: Arc<CommandOptions>,
+}
+
+impl CondenserEdit {
+    pub fn new() -> CondenserEdit {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(
+            "Change where a shortcode on this server's Condenser service points, optionally attaching a note. You \
+             can only edit codes you submitted, unless you're a bot owner or a superuser on the server the code was \
+             submitted in. Superusers on a server with its own Condenser service may edit any code."
+                .into(),
+        );
+        opts.usage = Some("CODE URL [NOTE...]".into());
+        opts.example = Some("google https://google.co.uk/ UK search".into());
+        opts.min_args = Some(2);
+
+        CondenserEdit {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for CondenserEdit {
+    fn execute(
+        &self,
+        ctx: &mut Context,
+        msg: &Message,
+        mut args: Args,
+    ) -> Result<(), CommandError> {
+        let code = match args.single::<String>() {
+            Ok(code) => code.to_uppercase(),
+            Err(_) => {
+                usage_error_embed(
+                    "condenser edit",
+                    "No code specified.",
+                    Arc::clone(&self.opts),
+                    msg,
+                );
+                return Ok(());
+            }
+        };
+
+        let url = match args.single::<Url>() {
+            Ok(url) => url,
+            Err(_) => {
+                usage_error_embed(
+                    "condenser edit",
+                    "Unable to parse provided URL.",
+                    Arc::clone(&self.opts),
+                    msg,
+                );
+                return Ok(());
+            }
+        };
+
+        if !check_scheme(&url, "condenser edit", &self.opts, msg) {
+            return Ok(());
+        }
+
+        let note = args.rest().trim().to_string();
+        let note = if note.is_empty() { None } else { Some(note) };
+
+        let editor_meta = submitter_meta(msg);
+        let rights = ManageRights::of(ctx, msg);
+
+        let client = match require_client(ctx, msg, true) {
+            Some(client) => client,
+            None => return Ok(()),
+        };
 
-            if parsed_response.status == "noexist" {
+        // Gather everything the closure will need here.
+        let usr_mention = msg.author.mention();
+        let author = msg.author.clone();
+        let channel_id = msg.channel_id;
+        let policy = policy_for(&ctx.data, msg.guild_id(), &client);
+
+        run_on_worker(move || {
+            // The existing metadata is always needed, as the submitter record is carried over.
+            let meta = match client.meta(&code) {
+                Ok(meta) => meta,
+                Err(err) => {
+                    handle_condenser_err(err, channel_id, &usr_mention, Some(&code));
+                    return;
+                }
+            };
+            let user_meta = meta.meta.user_meta.as_ref().map(|it| it.as_str());
+            if !rights.allows(user_meta, &author) {
                 error_embed(
                     &channel_id,
-                    "Code does not exist.",
+                    "You can only edit codes you submitted.",
                     Some(&usr_mention),
                     |e| e.field("Code", code, false),
                 );
                 return;
             }
 
+            if let Err(violation) = policy.check_redirects(&url) {
+                handle_policy_violation(violation, channel_id, &usr_mention, url.as_str());
+                return;
+            }
+            let url = url.into_string();
+
+            let new_meta = edited_meta(user_meta, &editor_meta, note.as_ref().map(|it| it.as_str()));
+            if let Err(err) = client.edit(&code, &url, Some(&new_meta)) {
+                handle_condenser_err(err, channel_id, &usr_mention, Some(&code));
+                return;
+            }
+
             let _ = channel_id.send_message(|m| {
                 m.content(usr_mention).embed(|e| {
-                    e.title("Code Deleted")
+                    e.title("Code Edited")
                         .colour(*COLOUR_CONDENSER)
                         .field("Code", code, false)
+                        .field("Old URL", truncate(meta.full_url.as_str(), EMBED_FIELD_LIMIT), false)
+                        .field("New URL", url, false)
+                })
+            });
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Whose codes `!condenser list` should show.
+enum ListTarget {
+    User(UserId),
+    All,
+}
+
+pub struct CondenserList {
+    opts: Arc<CommandOptions>,
+}
+
+impl CondenserList {
+    pub fn new() -> CondenserList {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(
+            "List the shortcodes you've created on this server's Condenser service. Bot owners may list another \
+             user's codes, or `all` codes."
+                .into(),
+        );
+        opts.usage = Some("[@USER | all] [PAGE]".into());
+        opts.example = Some("2".into());
+        opts.max_args = Some(2);
+
+        CondenserList {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for CondenserList {
+    fn execute(
+        &self,
+        ctx: &mut Context,
+        msg: &Message,
+        args: Args,
+    ) -> Result<(), CommandError> {
+        let mut target = ListTarget::User(msg.author.id);
+        let mut page = 1;
+        for arg in args.full().split_whitespace() {
+            if arg == "all" {
+                target = ListTarget::All;
+            } else if let Some(id) = parse_username(arg) {
+                target = ListTarget::User(UserId(id));
+            } else if let Ok(num) = arg.parse::<usize>() {
+                page = num.max(1);
+            } else {
+                usage_error_embed(
+                    "condenser list",
+                    &format!("Unrecognised argument: {}", arg),
+                    Arc::clone(&self.opts),
+                    msg,
+                );
+                return Ok(());
+            }
+        }
+
+        let is_self = match target {
+            ListTarget::User(id) => id == msg.author.id,
+            ListTarget::All => false,
+        };
+        if !is_self && !perms!(ctx.data).is_owner(msg.author.id) {
+            error_embed(
+                &msg.channel_id,
+                "Only bot owners can list other users' codes.",
+                None,
+                |e| e,
+            );
+            return Ok(());
+        }
+
+        let client = match require_client(ctx, msg, true) {
+            Some(client) => client,
+            None => return Ok(()),
+        };
+
+        // Gather everything the closure will need here.
+        let usr_mention = msg.author.mention();
+        let channel_id = msg.channel_id;
+
+        run_on_worker(move || {
+            let user = match target {
+                ListTarget::User(id) => match id.get() {
+                    Ok(user) => Some(user),
+                    Err(err) => {
+                        warn!("Unable to fetch user {}: {:?}", id, err);
+                        error_embed(&channel_id, "Unknown user.", Some(&usr_mention), |e| e);
+                        return;
+                    }
+                },
+                ListTarget::All => None,
+            };
+
+            let codes = match client.list() {
+                Ok(codes) => codes,
+                Err(err) => {
+                    handle_condenser_err(err, channel_id, &usr_mention, None);
+                    return;
+                }
+            };
+
+            let mut codes = codes
+                .into_iter()
+                .filter(|it| match user {
+                    Some(ref user) => submitted_by(it.meta.user_meta.as_ref().map(|it| it.as_str()), user),
+                    None => true,
+                })
+                .collect::<Vec<_>>();
+            codes.sort_by(|a, b| b.meta.time.cmp(&a.meta.time));
+
+            let pages = (codes.len() + LIST_PAGE_SIZE - 1) / LIST_PAGE_SIZE;
+            let title = match user {
+                Some(ref user) => format!("Codes submitted by {}", user.tag()),
+                None => "All codes".into(),
+            };
+
+            let _ = channel_id.send_message(|m| {
+                m.content(usr_mention).embed(|mut e| {
+                    e = e.title(title).colour(*COLOUR_CONDENSER);
+
+                    if codes.is_empty() {
+                        return e.description("No codes found.");
+                    }
+                    if page > pages {
+                        return e.description(format!("There are only {} pages.", pages));
+                    }
+
+                    e = e.description(format!("Page {} of {} ({} codes)", page, pages, codes.len()));
+                    for entry in codes.iter().skip((page - 1) * LIST_PAGE_SIZE).take(LIST_PAGE_SIZE) {
+                        let short_url = client
+                            .short_url(&entry.code)
+                            .map(|it| it.into_string())
+                            .unwrap_or_else(|_| entry.code.clone());
+                        e = e.field(
+                            short_url,
+                            format!(
+                                "{}\nCreated {}",
+                                truncate(entry.full_url.as_str(), LIST_URL_LENGTH),
+                                entry.meta.time.format("%d/%m/%Y at %H:%M:%S (%Z)")
+                            ),
+                            false,
+                        );
+                    }
+
+                    e
                 })
             });
         });
@@ -595,3 +1058,379 @@ impl Command for CondenserDelete {
         Arc::clone(&self.opts)
     }
 }
+
+/// Sets a guild's own Condenser server and API key.
+pub struct CondenserServerSet {
+    opts: Arc<CommandOptions>,
+}
+
+impl CondenserServerSet {
+    pub fn new() -> CondenserServerSet {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(
+            "Use a different Condenser server for this server. Without an API key, only `!condenser meta` will \
+             work. The message is deleted so the key isn't left in the channel."
+                .into(),
+        );
+        opts.usage = Some("URL [KEY]".into());
+        opts.example = Some("https://condenser.example.com/ 0123456789abcdef".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+        opts.max_args = Some(2);
+
+        CondenserServerSet {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for CondenserServerSet {
+    fn execute(&self, ctx: &mut Context, msg: &Message, mut args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        // Get the key out of the channel before anything else can go wrong.
+        let key = args.rest().split_whitespace().nth(1).map(String::from);
+        let key_visible = key.is_some() && msg.delete().is_err();
+
+        let server = match args.single::<Url>() {
+            Ok(url) => url,
+            Err(_) => {
+                usage_error_embed(
+                    "condenser server set",
+                    "Unable to parse provided URL.",
+                    Arc::clone(&self.opts),
+                    msg,
+                );
+                return Ok(());
+            }
+        };
+        if !check_scheme(&server, "condenser server set", &self.opts, msg) {
+            return Ok(());
+        }
+
+        let store = guild_conf!(ctx.data);
+        let has_key = key.is_some();
+        let res = store.update_section(guild_id, SECTION, |conf: &mut GuildCondenser| {
+            conf.server = Some(server.to_string());
+            conf.key = key;
+        });
+
+        if let Err(err) = res {
+            error!("Unable to save Condenser server for guild {}: {}", guild_id, err);
+            error_embed(&msg.channel_id, "Unable to save server. Ask your admin for assistance.", None, |e| e);
+            return Ok(());
+        }
+
+        let _ = msg.channel_id.send_message(|m| {
+            m.embed(|mut e| {
+                e = e.title("Condenser Server Set")
+                    .colour(*COLOUR_CONDENSER)
+                    .field("Server", server, false)
+                    .field("API Key", if has_key { "Set" } else { "Not set" }, true);
+                if key_visible {
+                    e = e.field(
+                        "Warning",
+                        "Unable to delete your message. Delete it yourself, as it contains the API key.",
+                        false,
+                    );
+                }
+                e
+            })
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Removes a guild's own Condenser server, returning it to the global one.
+pub struct CondenserServerReset {
+    opts: Arc<CommandOptions>,
+}
+
+impl CondenserServerReset {
+    pub fn new() -> CondenserServerReset {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Go back to using the bot's default Condenser server.".into());
+        opts.guild_only = true;
+        opts.max_args = Some(0);
+
+        CondenserServerReset {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for CondenserServerReset {
+    fn execute(&self, ctx: &mut Context, msg: &Message, _: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let store = guild_conf!(ctx.data);
+        let res = store.update_section(guild_id, SECTION, |conf: &mut GuildCondenser| {
+            conf.key = None;
+            conf.server.take()
+        });
+
+        match res {
+            Ok(Some(_)) => {
+                let _ = msg.channel_id.send_message(|m| {
+                    m.embed(|e| {
+                        e.title("Condenser Server Reset")
+                            .colour(*COLOUR_CONDENSER)
+                            .description("This server now uses the bot's default Condenser server.")
+                    })
+                });
+            }
+            Ok(None) => {
+                error_embed(&msg.channel_id, "This server already uses the default Condenser server.", None, |e| e);
+            }
+            Err(err) => {
+                error!("Unable to reset Condenser server for guild {}: {}", guild_id, err);
+                error_embed(&msg.channel_id, "Unable to reset server. Ask your admin for assistance.", None, |e| e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Shows which Condenser server a guild uses. Never shows the API key.
+pub struct CondenserServerShow {
+    opts: Arc<CommandOptions>,
+}
+
+impl CondenserServerShow {
+    pub fn new() -> CondenserServerShow {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Show which Condenser server this server uses.".into());
+        opts.guild_only = true;
+        opts.max_args = Some(0);
+
+        CondenserServerShow {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for CondenserServerShow {
+    fn execute(&self, ctx: &mut Context, msg: &Message, _: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let local: GuildCondenser = guild_conf!(ctx.data).get_section(guild_id, SECTION);
+        let source = if local.server.is_some() { "This server" } else { "Bot default" };
+        let client = client_for(&ctx.data, Some(guild_id), false);
+
+        let _ = msg.channel_id.send_message(|m| {
+            m.embed(|e| {
+                let e = e.title("Condenser Server").colour(*COLOUR_CONDENSER);
+                match client {
+                    Some(client) => e
+                        .field("Server", client.server(), false)
+                        .field("Configured By", source, true)
+                        .field("API Key", if client.has_key() { "Set" } else { "Not set" }, true),
+                    None => e.description("No Condenser server is configured."),
+                }
+            })
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// How `!condenser policy` changes a domain's listing.
+#[derive(Clone, Copy)]
+enum ListAction {
+    Allow,
+    Block,
+    Unlist,
+}
+
+/// Adds domains to, or removes them from, a guild's allowed and blocked domains.
+pub struct PolicyList {
+    opts: Arc<CommandOptions>,
+    action: ListAction,
+}
+
+impl PolicyList {
+    fn new(action: ListAction, desc: &str) -> PolicyList {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(desc.into());
+        opts.usage = Some("DOMAIN".into());
+        opts.example = Some("example.com".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+        opts.max_args = Some(1);
+
+        PolicyList {
+            opts: Arc::new(opts),
+            action,
+        }
+    }
+
+    pub fn allow() -> PolicyList {
+        PolicyList::new(
+            ListAction::Allow,
+            "Allow `!shorten` to link to a domain and its subdomains. Once any domain is allowed, all others are \
+             rejected.",
+        )
+    }
+
+    pub fn block() -> PolicyList {
+        PolicyList::new(
+            ListAction::Block,
+            "Stop `!shorten` linking to a domain and its subdomains.",
+        )
+    }
+
+    pub fn unlist() -> PolicyList {
+        PolicyList::new(
+            ListAction::Unlist,
+            "Remove a domain from the allowed and blocked domains.",
+        )
+    }
+
+    fn cmd_name(&self) -> &'static str {
+        match self.action {
+            ListAction::Allow => "condenser policy allow",
+            ListAction::Block => "condenser policy block",
+            ListAction::Unlist => "condenser policy unlist",
+        }
+    }
+}
+
+impl Command for PolicyList {
+    fn execute(&self, ctx: &mut Context, msg: &Message, args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let domain = match normalise_domain(args.full()) {
+            Some(domain) => domain,
+            None => {
+                usage_error_embed(self.cmd_name(), "That isn't a valid domain.", Arc::clone(&self.opts), msg);
+                return Ok(());
+            }
+        };
+
+        let action = self.action;
+        let store = guild_conf!(ctx.data);
+        let res = store.update_section(guild_id, POLICY_SECTION, |lists: &mut DomainLists| {
+            let listed = lists.allowed.contains(&domain) || lists.blocked.contains(&domain);
+            lists.allowed.retain(|it| *it != domain);
+            lists.blocked.retain(|it| *it != domain);
+            match action {
+                ListAction::Allow => lists.allowed.push(domain.clone()),
+                ListAction::Block => lists.blocked.push(domain.clone()),
+                ListAction::Unlist => {}
+            }
+            listed
+        });
+
+        let title = match (action, res) {
+            (ListAction::Allow, Ok(_)) => "Domain Allowed",
+            (ListAction::Block, Ok(_)) => "Domain Blocked",
+            (ListAction::Unlist, Ok(true)) => "Domain Unlisted",
+            (ListAction::Unlist, Ok(false)) => {
+                error_embed(&msg.channel_id, "That domain isn't allowed or blocked.", None, |e| e);
+                return Ok(());
+            }
+            (_, Err(err)) => {
+                error!("Unable to save link policy for guild {}: {}", guild_id, err);
+                error_embed(&msg.channel_id, "Unable to save link policy. Ask your admin for assistance.", None, |e| e);
+                return Ok(());
+            }
+        };
+
+        let _ = msg.channel_id.send_message(|m| {
+            m.embed(|e| e.title(title).colour(*COLOUR_CONDENSER).field("Domain", domain, false))
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Shows a guild's link policy.
+pub struct PolicyShow {
+    opts: Arc<CommandOptions>,
+}
+
+impl PolicyShow {
+    pub fn new() -> PolicyShow {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(
+            "Show which links `!shorten` accepts here. IP addresses, other shorteners and the maximum length are \
+             set with `!svar`."
+                .into(),
+        );
+        opts.guild_only = true;
+        opts.max_args = Some(0);
+
+        PolicyShow {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for PolicyShow {
+    fn execute(&self, ctx: &mut Context, msg: &Message, _: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let store = guild_conf!(ctx.data);
+        let lists: DomainLists = store.get_section(guild_id, POLICY_SECTION);
+        let render = |domains: &[String], empty: &str| {
+            if domains.is_empty() {
+                empty.to_string()
+            } else {
+                let list = domains.iter().map(|it| format!("`{}`", it)).collect::<Vec<_>>().join(", ");
+                truncate(&list, EMBED_FIELD_LIMIT)
+            }
+        };
+        let yes_no = |allowed: bool| if allowed { "Allowed" } else { "Rejected" };
+
+        let _ = msg.channel_id.send_message(|m| {
+            m.embed(|e| {
+                e.title("Link Policy")
+                    .colour(*COLOUR_CONDENSER)
+                    .field("Allowed Domains", render(&lists.allowed, "Any"), false)
+                    .field("Blocked Domains", render(&lists.blocked, "None"), false)
+                    .field("IP Addresses", yes_no(store.get::<SVarCondenserAllowIpHosts>(guild_id)), true)
+                    .field("Other Shorteners", yes_no(store.get::<SVarCondenserAllowShortLinks>(guild_id)), true)
+                    .field("Maximum Length", store.get::<SVarCondenserMaxUrlLength>(guild_id), true)
+            })
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
@@ -0,0 +1,535 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, Utc};
+use parking_lot::Mutex;
+use serenity::framework::standard::{Args, Command, CommandError, CommandOptions};
+use serenity::http;
+use serenity::model::channel::Message;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::prelude::{Context, Mentionable};
+use serenity::utils::parse_username;
+use serenity::CACHE;
+
+use constants::COLOUR_PRIMARY;
+use server::config::ConfigStore;
+use server::svar::SVarMuteRole;
+use types::{GuildConfigMarker, PermissionsMarker};
+use utils::{error_embed, format_duration, parse_duration, require_guild, usage_error_embed};
+
+/// Name of the infractions section in each guild's configuration.
+const SECTION: &str = "infractions";
+
+/// How often to check for expired mutes and bans.
+const EXPIRY_CHECK_SECS: u64 = 60;
+
+/// Discord rejects audit log reasons longer than this.
+const MAX_AUDIT_REASON: usize = 512;
+
+/// Maximum number of infractions to list at once.
+const MAX_LISTED: usize = 10;
+
+lazy_static! {
+    // Held while punishing or lifting, so a lift can't undo a punishment issued at the same moment.
+    static ref DISCIPLINE_LOCK: Mutex<()> = Mutex::new(());
+    // When each guild's next timed mute or ban expires, so the expiry thread only reads guilds with something due.
+    static ref NEXT_EXPIRY: Mutex<HashMap<GuildId, DateTime<Utc>>> = Mutex::new(HashMap::new());
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+#[serde(rename_all = "lowercase")]
+enum Action {
+    Warn,
+    Mute,
+    Kick,
+    Ban,
+}
+
+impl Action {
+    fn command(&self) -> &'static str {
+        match *self {
+            Action::Warn => "warn",
+            Action::Mute => "mute",
+            Action::Kick => "kick",
+            Action::Ban => "ban",
+        }
+    }
+
+    fn noun(&self) -> &'static str {
+        match *self {
+            Action::Warn => "Warning",
+            Action::Mute => "Mute",
+            Action::Kick => "Kick",
+            Action::Ban => "Ban",
+        }
+    }
+
+    fn past_tense(&self) -> &'static str {
+        match *self {
+            Action::Warn => "Warned",
+            Action::Mute => "Muted",
+            Action::Kick => "Kicked",
+            Action::Ban => "Banned",
+        }
+    }
+
+    /// Whether the action can be given a duration, after which it is lifted.
+    fn timed(&self) -> bool {
+        *self == Action::Mute || *self == Action::Ban
+    }
+}
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct InfractionLog {
+    /// Case number to assign to the next record. Numbers are never reused.
+    #[serde(default)]
+    next_id: u64,
+    #[serde(default)]
+    records: Vec<Infraction>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Infraction {
+    id: u64,
+    action: Action,
+    user: u64,
+    moderator: u64,
+    reason: String,
+    time: DateTime<Utc>,
+    /// When a timed mute or ban should be lifted. Permanent if unset.
+    #[serde(default)]
+    expires: Option<DateTime<Utc>>,
+    /// The role applied by a mute, so it can still be removed if the role is renamed.
+    #[serde(default)]
+    role: Option<u64>,
+    /// Whether a timed mute or ban has been lifted, or superseded by a later one.
+    #[serde(default)]
+    lifted: bool,
+}
+
+/// Parses `@USER [DURATION] [REASON...]`, only accepting a duration if `timed` is set.
+fn parse_target(input: &str, timed: bool) -> Result<(UserId, Option<Duration>, String), &'static str> {
+    let mut parts = input.trim().splitn(2, char::is_whitespace);
+    let user = parts
+        .next()
+        .and_then(|it| parse_username(it).or_else(|| it.parse().ok()))
+        .map(UserId)
+        .ok_or("You must mention a member.")?;
+    let mut rest = parts.next().unwrap_or("").trim();
+
+    let mut duration = None;
+    if timed {
+        let mut parts = rest.splitn(2, char::is_whitespace);
+        if let Some(parsed) = parts.next().and_then(parse_duration) {
+            duration = Some(parsed);
+            rest = parts.next().unwrap_or("").trim();
+        }
+    }
+
+    let reason = if rest.is_empty() { "No reason given." } else { rest };
+    Ok((user, duration, reason.to_string()))
+}
+
+/// Finds the guild's mute role by name.
+fn find_mute_role(guild_id: GuildId, name: &str) -> Option<RoleId> {
+    let guild = guild_id.find()?;
+    let guild = guild.read();
+    guild.roles.values().find(|it| it.name == name).map(|it| it.id)
+}
+
+/// Serenity command for warning, muting, kicking or banning a member. Every action is recorded.
+pub struct Discipline {
+    opts: Arc<CommandOptions>,
+    action: Action,
+}
+
+impl Discipline {
+    pub fn warn() -> Discipline {
+        Discipline::new(Action::Warn, "Warn a member. The warning is recorded against them.")
+    }
+
+    pub fn mute() -> Discipline {
+        Discipline::new(
+            Action::Mute,
+            "Mute a member by giving them the role named by the `mute_role` SVar, optionally for a limited time.",
+        )
+    }
+
+    pub fn kick() -> Discipline {
+        Discipline::new(Action::Kick, "Kick a member from the server.")
+    }
+
+    pub fn ban() -> Discipline {
+        Discipline::new(Action::Ban, "Ban a member from the server, optionally for a limited time.")
+    }
+
+    fn new(action: Action, desc: &str) -> Discipline {
+        let mut opts = CommandOptions::default();
+        if action.timed() {
+            opts.desc = Some(format!(
+                "{} Durations look like `30m`, `12h` or `1d12h`; omit the duration to make it permanent.",
+                desc
+            ));
+            opts.usage = Some("@USER [DURATION] [REASON...]".into());
+            opts.example = Some("@Spammer 1d Posting invite links".into());
+        } else {
+            opts.desc = Some(desc.into());
+            opts.usage = Some("@USER [REASON...]".into());
+            opts.example = Some("@Spammer Posting invite links".into());
+        }
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+
+        Discipline {
+            opts: Arc::new(opts),
+            action,
+        }
+    }
+
+    /// Carries out the action on Discord, returning a user-presentable error on failure. Kicks can't carry an audit
+    /// log reason, but the reason is kept in the infraction record either way.
+    fn apply(&self, guild_id: GuildId, user: UserId, reason: &str, mute_role: Option<RoleId>) -> Result<(), String> {
+        let res = match self.action {
+            Action::Warn => Ok(()),
+            Action::Mute => match mute_role {
+                Some(role) => http::add_member_role(guild_id.0, user.0, role.0),
+                None => Ok(()),
+            },
+            Action::Kick => guild_id.kick(user),
+            Action::Ban => {
+                let reason = reason.chars().take(MAX_AUDIT_REASON).collect::<String>();
+                guild_id.ban(user, &(0, reason.as_str()))
+            }
+        };
+
+        res.map_err(|err| {
+            warn!("Unable to {} {} in guild {}: {:?}", self.action.command(), user, guild_id, err);
+            format!(
+                "Unable to {} that member. Check the bot's permissions and role position.",
+                self.action.command()
+            )
+        })
+    }
+}
+
+impl Command for Discipline {
+    fn execute(&self, ctx: &mut Context, msg: &Message, args: Args) -> Result<(), CommandError> {
+        let cmd_name = self.action.command();
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let (user, duration, reason) = match parse_target(args.full(), self.action.timed()) {
+            Ok(target) => target,
+            Err(err) => {
+                usage_error_embed(cmd_name, err, Arc::clone(&self.opts), msg);
+                return Ok(());
+            }
+        };
+
+        // Don't let moderators act on themselves, the bot, or anyone with at least their own permissions.
+        let perms = perms!(ctx.data);
+        if user == msg.author.id || user == CACHE.read().user.id
+            || perms.level(user, Some(guild_id)) >= perms.level(msg.author.id, Some(guild_id))
+        {
+            error_embed(&msg.channel_id, "You can't do that to this member.", None, |e| e);
+            return Ok(());
+        }
+
+        let store = guild_conf!(ctx.data);
+        let mute_role = if self.action == Action::Mute {
+            let name = store.get::<SVarMuteRole>(guild_id);
+            match find_mute_role(guild_id, &name) {
+                Some(role) => Some(role),
+                None => {
+                    error_embed(
+                        &msg.channel_id,
+                        &format!(
+                            "There is no role named `{}`. Create one, or change the `mute_role` SVar.",
+                            name
+                        ),
+                        None,
+                        |e| e,
+                    );
+                    return Ok(());
+                }
+            }
+        } else {
+            None
+        };
+
+        let guard = DISCIPLINE_LOCK.lock();
+        if let Err(err) = self.apply(guild_id, user, &reason, mute_role) {
+            error_embed(&msg.channel_id, &err, None, |e| e);
+            return Ok(());
+        }
+
+        let action = self.action;
+        let moderator = msg.author.id.0;
+        let now = Utc::now();
+        let expires = duration.map(|it| now + it);
+        let res = store.update_section(guild_id, SECTION, |log: &mut InfractionLog| {
+            // A new mute or ban replaces any earlier timed one, so the earlier expiry doesn't lift it early.
+            for record in &mut log.records {
+                if record.action == action && record.user == user.0 && record.expires.is_some() {
+                    record.lifted = true;
+                }
+            }
+
+            log.next_id += 1;
+            log.records.push(Infraction {
+                id: log.next_id,
+                action,
+                user: user.0,
+                moderator,
+                reason: reason.clone(),
+                time: now,
+                expires,
+                role: mute_role.map(|it| it.0),
+                lifted: false,
+            });
+            log.next_id
+        });
+        drop(guard);
+
+        let case = match res {
+            Ok(case) => {
+                if let Some(expires) = expires {
+                    schedule_expiry(guild_id, expires);
+                }
+                case
+            }
+            Err(err) => {
+                error!("Unable to save infraction for guild {}: {}", guild_id, err);
+                error_embed(
+                    &msg.channel_id,
+                    "The action was taken, but couldn't be recorded. Ask your admin for assistance.",
+                    None,
+                    |e| e,
+                );
+                return Ok(());
+            }
+        };
+
+        info!(
+            "{} {} in guild {} by {} (case #{}): {}",
+            action.past_tense(),
+            user,
+            guild_id,
+            msg.author.tag(),
+            case,
+            reason
+        );
+        let _ = msg.channel_id.send_message(|m| {
+            m.embed(|mut e| {
+                e = e.title(format!("Member {} (Case #{})", action.past_tense(), case))
+                    .colour(*COLOUR_PRIMARY)
+                    .field("Member", user.mention(), true)
+                    .field("Moderator", msg.author.mention(), true);
+                if action.timed() {
+                    let duration = duration.map(format_duration).unwrap_or_else(|| "Permanent".into());
+                    e = e.field("Duration", duration, true);
+                }
+                e.field("Reason", &reason, false)
+            })
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Serenity command for listing a member's recorded infractions.
+pub struct Infractions {
+    opts: Arc<CommandOptions>,
+}
+
+impl Infractions {
+    pub fn new() -> Infractions {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(format!(
+            "List a member's warnings, mutes, kicks and bans, most recent first (up to {}).",
+            MAX_LISTED
+        ));
+        opts.usage = Some("@USER".into());
+        opts.example = Some("@Spammer".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+        opts.max_args = Some(1);
+
+        Infractions {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for Infractions {
+    fn execute(&self, ctx: &mut Context, msg: &Message, args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let user = match parse_target(args.full(), false) {
+            Ok((user, _, _)) => user,
+            Err(err) => {
+                usage_error_embed("infractions", err, Arc::clone(&self.opts), msg);
+                return Ok(());
+            }
+        };
+
+        let store = guild_conf!(ctx.data);
+        let log: InfractionLog = store.get_section(guild_id, SECTION);
+        let records = log.records
+            .iter()
+            .rev()
+            .filter(|it| it.user == user.0)
+            .collect::<Vec<_>>();
+
+        let now = Utc::now();
+        let _ = msg.channel_id.send_message(|m| {
+            m.embed(|mut e| {
+                e = e.title("Infractions")
+                    .colour(*COLOUR_PRIMARY)
+                    .description(format!("{} has {} recorded infractions.", user.mention(), records.len()));
+
+                for record in records.iter().take(MAX_LISTED) {
+                    let mut detail = format!("{} (by {})", record.reason, UserId(record.moderator).mention());
+                    if record.action.timed() {
+                        let status = match record.expires {
+                            None => "permanent".to_string(),
+                            Some(_) if record.lifted => "lifted".to_string(),
+                            Some(expires) if expires <= now => "expiring".to_string(),
+                            Some(expires) => format!("{} left", format_duration(expires.signed_duration_since(now))),
+                        };
+                        detail = format!("{}\n*{}*", detail, status);
+                    }
+
+                    e = e.field(
+                        format!(
+                            "#{} {} on {}",
+                            record.id,
+                            record.action.noun(),
+                            record.time.format("%d/%m/%Y at %H:%M:%S (%Z)")
+                        ),
+                        detail,
+                        false,
+                    );
+                }
+                e
+            })
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Starts a background thread which lifts timed mutes and bans once they expire. Expiry times are read from each
+/// guild's stored infractions on startup, so pending lifts survive a restart.
+pub fn start_expiry_thread(store: Arc<ConfigStore>) {
+    thread::Builder::new()
+        .name("discipline-expiry".into())
+        .spawn(move || {
+            for guild_id in store.guilds() {
+                let log: InfractionLog = store.get_section(guild_id, SECTION);
+                if let Some(next) = next_expiry(&log) {
+                    schedule_expiry(guild_id, next);
+                }
+            }
+
+            loop {
+                lift_expired(&store);
+                thread::sleep(StdDuration::from_secs(EXPIRY_CHECK_SECS));
+            }
+        })
+        .expect("Unable to start discipline expiry thread.");
+}
+
+/// Notes that a guild has a mute or ban expiring at the given time.
+fn schedule_expiry(guild_id: GuildId, at: DateTime<Utc>) {
+    let mut next = NEXT_EXPIRY.lock();
+    let entry = next.entry(guild_id).or_insert(at);
+    if at < *entry {
+        *entry = at;
+    }
+}
+
+/// Finds when the earliest pending mute or ban in a guild expires.
+fn next_expiry(log: &InfractionLog) -> Option<DateTime<Utc>> {
+    log.records.iter().filter(|it| !it.lifted).filter_map(|it| it.expires).min()
+}
+
+fn lift_expired(store: &ConfigStore) {
+    let now = Utc::now();
+    let due = {
+        let mut next = NEXT_EXPIRY.lock();
+        let due = next.iter().filter(|&(_, at)| *at <= now).map(|(guild_id, _)| *guild_id).collect::<Vec<_>>();
+        for guild_id in &due {
+            next.remove(guild_id);
+        }
+        due
+    };
+
+    for guild_id in due {
+        lift_guild(store, guild_id, now);
+    }
+}
+
+/// Lifts a guild's mutes and bans which have expired by `now`.
+fn lift_guild(store: &ConfigStore, guild_id: GuildId, now: DateTime<Utc>) {
+    let _guard = DISCIPLINE_LOCK.lock();
+
+    // Records are marked lifted before lifting, and only if they still need it, so nothing is lifted twice. They're
+    // marked even if lifting fails (e.g. the member has since left), so we don't retry forever.
+    let res = store.update_section(guild_id, SECTION, |log: &mut InfractionLog| {
+        let mut due = Vec::new();
+        for record in &mut log.records {
+            if !record.lifted && record.expires.map_or(false, |expires| expires <= now) {
+                record.lifted = true;
+                due.push(record.clone());
+            }
+        }
+        (due, next_expiry(log))
+    });
+
+    let due = match res {
+        Ok((due, next)) => {
+            if let Some(next) = next {
+                schedule_expiry(guild_id, next);
+            }
+            due
+        }
+        Err(err) => {
+            error!("Unable to save infractions for guild {}: {}", guild_id, err);
+            schedule_expiry(guild_id, now);
+            return;
+        }
+    };
+
+    for record in due {
+        let res = match (record.action, record.role) {
+            (Action::Mute, Some(role)) => http::remove_member_role(guild_id.0, record.user, role),
+            (Action::Ban, _) => guild_id.unban(UserId(record.user)),
+            _ => Ok(()),
+        };
+
+        match res {
+            Ok(()) => info!("Lifted {} #{} in guild {}", record.action.command(), record.id, guild_id),
+            Err(err) => warn!(
+                "Unable to lift {} #{} in guild {}: {:?}",
+                record.action.command(),
+                record.id,
+                guild_id,
+                err
+            ),
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::Arc;
+
+use rand::{self, Rng};
+use serenity::framework::standard::{Args, Command, CommandError, CommandOptions};
+use serenity::model::channel::Message;
+use serenity::prelude::{Context, Mentionable};
+
+use constants::{COLOUR_GAMES, EMBED_DESCRIPTION_LIMIT};
+use dice::{Expression, RollResult, TermResult};
+use server::svar::{SVar, SVarRollDetail, SVarRollMax, SVarRollMin, SVarUseGames};
+use types::GuildConfigMarker;
+use utils::{error_embed, truncate, usage_error_embed};
+
+/// Maximum number of individual dice to list in a reply. Larger rolls only show the total for each group.
+const MAX_SHOWN_DICE: usize = 50;
+
+/// Renders each term of a roll, e.g. `4d6kh3 [6, 5, ~~2~~, 4]`, joined by its sign. If `summarise` is set, only the
+/// total of each group of dice is shown.
+fn render_terms(result: &RollResult, summarise: bool) -> String {
+    let mut out = String::new();
+    for (idx, &(negative, ref term)) in result.terms.iter().enumerate() {
+        if idx > 0 || negative {
+            out.push_str(if negative { " - " } else { " + " });
+        }
+
+        match *term {
+            TermResult::Constant(value) => out.push_str(&value.to_string()),
+            TermResult::Dice(ref group, ref dice) => {
+                out.push_str(&format!("{} ", group));
+                if summarise || dice.len() > MAX_SHOWN_DICE {
+                    out.push_str(&format!("[{} dice totalling {}]", dice.len(), term.value()));
+                    continue;
+                }
+
+                let dice = dice.iter()
+                    .map(|it| {
+                        let value = if it.exploded { format!("{}!", it.value) } else { it.value.to_string() };
+                        if it.kept { value } else { format!("~~{}~~", value) }
+                    })
+                    .collect::<Vec<_>>();
+                out.push_str(&format!("[{}]", dice.join(", ")));
+            }
+        }
+    }
+    out
+}
+
+/// Serenity command for rolling numbers and dice.
+pub struct Roll {
+    opts: Arc<CommandOptions>,
+}
+
+impl Roll {
+    pub fn new() -> Roll {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(
+            "Roll a random number or some dice. With no arguments, rolls between this server's default minimum and \
+             maximum. Give one number to roll between 1 and that number, or two for a custom range. Dice expressions \
+             support `NdM`, `+`/`-` modifiers, keeping or dropping dice (`kh`, `kl`, `dh`, `dl`), exploding dice (`!`) \
+             and `adv`/`dis` for advantage and disadvantage."
+                .into(),
+        );
+        opts.usage = Some("[MAX | MIN MAX | DICE]".into());
+        opts.example = Some("4d6kh3".into());
+
+        Roll {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for Roll {
+    fn execute(&self, ctx: &mut Context, msg: &Message, args: Args) -> Result<(), CommandError> {
+        let (mut min, mut max) = (SVarRollMin::get_default(), SVarRollMax::get_default());
+        let mut detail = SVarRollDetail::get_default();
+        if let Some(guild_id) = msg.guild_id() {
+            let store = guild_conf!(ctx.data);
+            if !store.get::<SVarUseGames>(guild_id) {
+                error_embed(&msg.channel_id, "Games are disabled on this server.", None, |e| e);
+                return Ok(());
+            }
+            min = store.get::<SVarRollMin>(guild_id);
+            max = store.get::<SVarRollMax>(guild_id);
+            detail = store.get::<SVarRollDetail>(guild_id);
+        }
+
+        let full = args.full().trim();
+        let numbers = full.split_whitespace().map(|it| it.parse::<i64>()).collect::<Vec<_>>();
+        let range = match numbers.as_slice() {
+            [] => Some((min, max)),
+            [Ok(max)] => Some((1, *max)),
+            [Ok(min), Ok(max)] => Some((*min, *max)),
+            _ => None,
+        };
+
+        let usr_mention = msg.author.mention();
+        if let Some((min, max)) = range {
+            let upper = match max.checked_add(1) {
+                Some(upper) if min < max => upper,
+                _ => {
+                    usage_error_embed("roll", "The minimum must be less than the maximum.", Arc::clone(&self.opts), msg);
+                    return Ok(());
+                }
+            };
+
+            let value = rand::thread_rng().gen_range(min, upper);
+            let _ = msg.channel_id.send_message(|m| {
+                m.content(usr_mention).embed(|e| {
+                    e.title(format!("Rolled {}", value))
+                        .colour(*COLOUR_GAMES)
+                        .description(format!("Between {} and {}.", min, max))
+                })
+            });
+            return Ok(());
+        }
+
+        let expr = match full.parse::<Expression>() {
+            Ok(expr) => expr,
+            Err(err) => {
+                usage_error_embed("roll", &format!("Invalid dice: {}.", err), Arc::clone(&self.opts), msg);
+                return Ok(());
+            }
+        };
+
+        let result = expr.roll(&mut rand::thread_rng());
+        let summarise = detail == "summary";
+        let mut terms = render_terms(&result, summarise);
+        if !summarise && terms.chars().count() > EMBED_DESCRIPTION_LIMIT {
+            terms = render_terms(&result, true);
+        }
+        // Even the summary can be too long for expressions with many terms.
+        let terms = truncate(&terms, EMBED_DESCRIPTION_LIMIT);
+
+        let _ = msg.channel_id.send_message(|m| {
+            m.content(usr_mention).embed(|e| {
+                e.title(format!("Rolled {}", result.total))
+                    .colour(*COLOUR_GAMES)
+                    .description(terms)
+            })
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
@@ -14,6 +14,8 @@ use serenity::model::id::ChannelId;
 use serenity::prelude::*;
 use serenity::utils::Colour;
 
+use types::PermissionsMarker;
+
 // This makes sure we're always satisfying HelpFunction.
 #[allow(non_upper_case_globals)]
 pub const drakonid_help: HelpFunction = with_embeds;
@@ -42,12 +44,25 @@ fn remove_aliases(cmds: &HashMap<String, CommandOrAlias>) -> HashMap<&String, &A
 }
 
 fn with_embeds<H: BuildHasher>(
-    _: &mut Context,
+    ctx: &mut Context,
     msg: &Message,
     help_options: &HelpOptions,
     groups: HashMap<String, Arc<CommandGroup>, H>,
     args: &Args,
 ) -> Result<(), CommandError> {
+    // arkan: Also check our own permission levels, which Serenity doesn't know about.
+    let perms = perms!(ctx.data);
+    let guild_id = msg.guild_id();
+    let level = perms.level(msg.author.id, guild_id);
+    let has_level = |group: &CommandGroup, name: &str| {
+        let full_name = if let Some(ref prefix) = group.prefix {
+            format!("{} {}", prefix, name)
+        } else {
+            name.to_string()
+        };
+        level >= perms.required_level(&full_name, guild_id)
+    };
+
     if !args.is_empty() {
         let name = args.full();
 
@@ -64,7 +79,7 @@ fn with_embeds<H: BuildHasher>(
                 if name == with_prefix || name == *command_name {
                     match *command {
                         CommandOrAlias::Command(ref cmd) => {
-                            if has_all_requirements(&cmd.options(), msg) {
+                            if has_all_requirements(&cmd.options(), msg) && has_level(&*group, command_name.as_str()) {
                                 found = Some((command_name, cmd));
                             } else {
                                 break;
@@ -75,7 +90,7 @@ fn with_embeds<H: BuildHasher>(
 
                             match *actual_command {
                                 CommandOrAlias::Command(ref cmd) => {
-                                    if has_all_requirements(&cmd.options(), msg) {
+                                    if has_all_requirements(&cmd.options(), msg) && has_level(&*group, name.as_str()) {
                                         found = Some((name, cmd));
                                     } else {
                                         break;
@@ -202,10 +217,12 @@ fn with_embeds<H: BuildHasher>(
                     let cmd = &commands[name];
                     let cmd = cmd.options();
 
+                    let allowed = has_level(&**group, name.as_str());
+
                     if !cmd.dm_only && !cmd.guild_only || cmd.dm_only && msg.is_private()
                         || cmd.guild_only && !msg.is_private()
                     {
-                        if cmd.help_available && has_correct_permissions(&cmd, msg) {
+                        if cmd.help_available && has_correct_permissions(&cmd, msg) && allowed {
                             if let Some(guild) = msg.guild() {
                                 let guild = guild.read();
 
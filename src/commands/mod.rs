@@ -6,13 +6,26 @@ use serenity::utils::Colour;
 use std::process;
 use std::sync::Arc;
 
+pub mod announcements;
 mod condenser;
+pub mod discipline;
+mod games;
 mod help;
-mod unimplemented;
+pub mod moderation;
+mod perm;
+mod quotes;
+pub mod snark;
+pub mod svar;
+mod wow;
 
-use self::unimplemented::UnimplementedCommand;
 use constants;
-use types::ConfigMarker;
+use server::permissions;
+use server::svar::{
+    SVarAllowNormalCensus, SVarAllowNormalCondenser, SVarAllowNormalQuotes, SVarAllowNormalShowme, SVarDiscAllowSu,
+    SVarRmHistAllowSu,
+};
+use types::{ConfigMarker, PermissionsMarker};
+use utils::error_embed;
 
 static mut SHARD_MANAGER: Option<Arc<Mutex<ShardManager>>> = None;
 
@@ -29,6 +42,7 @@ fn shutdown_bot() {
 pub fn attach_framework(client: &mut Client) {
     let cdata = Arc::clone(&client.data);
     let conf = Arc::clone(cdata.lock().get::<ConfigMarker>().unwrap());
+    let perms = Arc::clone(cdata.lock().get::<PermissionsMarker>().unwrap());
 
     // This is technically unsafe, but we know it's always safe.
     // We store this so the stop command can access it - exec's type signature disallows capturing closures.
@@ -58,51 +72,141 @@ pub fn attach_framework(client: &mut Client) {
             .command_not_found_text(":skull_crossbones: Command `{}` does not exist.")
             .embed_success_colour(Colour::orange())
         )
-        // Command logger
-        .before(|_ctx, msg, cmd_name| {
+        // Command logger and permission check
+        .before(|ctx, msg, cmd_name| {
             debug!("Command execution: '{}' from {} ('{}')", cmd_name, msg.author.id, msg.author.name);
-            true // We're not a check, so always approve messages.
+
+            let perms = perms!(ctx.data);
+            let guild_id = msg.guild_id();
+            let required = perms.required_level(cmd_name, guild_id);
+            if perms.level(msg.author.id, guild_id) < required {
+                debug!("Rejected '{}' from {}: requires {}", cmd_name, msg.author.id, required);
+                snark::command_failed(&ctx.data, msg);
+                error_embed(
+                    &msg.channel_id,
+                    &format!("You don't have permission to use this command here (requires {}).", required),
+                    None,
+                    |e| e,
+                );
+                return false;
+            }
+
+            true
+        })
+        // Snark tracking for failed commands
+        .after(|ctx, msg, _cmd_name, res| match res {
+            Ok(()) => snark::command_succeeded(&ctx.data, msg),
+            Err(_) => snark::command_failed(&ctx.data, msg),
         })
+        .unrecognised_command(|ctx, msg, _cmd_name| snark::command_failed(&ctx.data, msg))
+        .on_dispatch_error(|ctx, msg, _err| snark::command_failed(&ctx.data, &msg))
 
         // Add buckets below here
         .bucket("ping", 0, 2, 10)
 
         // Add commands/groups below here
-        .group("Actions", |mut group| {
-            if let Some(shorten) = condenser::CondenserShorten::new(&cdata) {
-                group = group.cmd("shorten", shorten);
-            }
+        .group("Actions", |group| {
+            perms.require("shorten", permissions::allow_normal_if::<SVarAllowNormalCondenser>);
+            group.cmd("shorten", condenser::CondenserShorten::new())
+        })
+        .group("Announcements", |group| {
+            perms.require("ann join set", permissions::superuser);
+            perms.require("ann join del", permissions::superuser);
+            perms.require("ann stream set", permissions::superuser);
+            perms.require("ann stream del", permissions::superuser);
             group
+                .prefix("ann")
+                .cmd("join set", announcements::JoinSet::new())
+                .cmd("join del", announcements::JoinDel::new())
+                .cmd("stream set", announcements::StreamSet::new())
+                .cmd("stream del", announcements::StreamDel::new())
         })
-        .group("Announcements", |group| group
-            .prefix("ann")
-            // TODO: Attach anouncements commands here.
-            .cmd("join set", UnimplementedCommand::new())
-            .cmd("join del", UnimplementedCommand::new())
-            .cmd("stream set", UnimplementedCommand::new())
-            .cmd("stream del", UnimplementedCommand::new())
-        )
-        .group("Condenser", |mut group| {
-            group = group.prefix("condenser");
-            if let Some(meta) = condenser::CondenserMeta::new(&cdata) {
-                group = group.cmd("meta", meta);
+        .group("Condenser", |group| {
+            perms.require("condenser server set", permissions::owner);
+            perms.require("condenser server reset", permissions::owner);
+            perms.require("condenser server show", permissions::owner);
+            perms.require("condenser policy allow", permissions::superuser);
+            perms.require("condenser policy block", permissions::superuser);
+            perms.require("condenser policy unlist", permissions::superuser);
+            perms.require("condenser policy show", permissions::superuser);
+            group
+                .prefix("condenser")
+                .cmd("meta", condenser::CondenserMeta::new())
+                .cmd("edit", condenser::CondenserEdit::new())
+                .cmd("delete", condenser::CondenserDelete::new())
+                .cmd("list", condenser::CondenserList::new())
+                .cmd("server set", condenser::CondenserServerSet::new())
+                .cmd("server reset", condenser::CondenserServerReset::new())
+                .cmd("server show", condenser::CondenserServerShow::new())
+                .cmd("policy allow", condenser::PolicyList::allow())
+                .cmd("policy block", condenser::PolicyList::block())
+                .cmd("policy unlist", condenser::PolicyList::unlist())
+                .cmd("policy show", condenser::PolicyShow::new())
+        })
+        .group("Games", |group| group.cmd("roll", games::Roll::new()))
+        .group("Moderation", |group| {
+            perms.require("rmhist", permissions::superuser_if::<SVarRmHistAllowSu>);
+            perms.require("warn", permissions::superuser_if::<SVarDiscAllowSu>);
+            perms.require("mute", permissions::superuser_if::<SVarDiscAllowSu>);
+            perms.require("kick", permissions::superuser_if::<SVarDiscAllowSu>);
+            perms.require("ban", permissions::superuser_if::<SVarDiscAllowSu>);
+            perms.require("infractions", permissions::superuser_if::<SVarDiscAllowSu>);
+            group
+                .cmd("rmhist", moderation::RmHist::new())
+                .cmd("warn", discipline::Discipline::warn())
+                .cmd("mute", discipline::Discipline::mute())
+                .cmd("kick", discipline::Discipline::kick())
+                .cmd("ban", discipline::Discipline::ban())
+                .cmd("infractions", discipline::Infractions::new())
+        })
+        .group("Permissions", |group| {
+            perms.require("perm set", permissions::superuser);
+            perms.require("perm remove", permissions::superuser);
+            perms.require("perm list", permissions::superuser);
+            group
+                .prefix("perm")
+                .cmd("set", perm::PermSet::new())
+                .cmd("remove", perm::PermRemove::new())
+                .cmd("list", perm::PermList::new())
+        })
+        .group("Quotes", |group| {
+            perms.require("quotes add", permissions::allow_normal_if::<SVarAllowNormalQuotes>);
+            perms.require("quotes get", permissions::allow_normal_if::<SVarAllowNormalQuotes>);
+            perms.require("quotes random", permissions::allow_normal_if::<SVarAllowNormalQuotes>);
+            perms.require("quotes search", permissions::allow_normal_if::<SVarAllowNormalQuotes>);
+            perms.require("quotes del", permissions::superuser);
+            group
+                .prefix("quotes")
+                .cmd("add", quotes::QuotesAdd::new())
+                .cmd("get", quotes::QuotesGet::new())
+                .cmd("random", quotes::QuotesRandom::new())
+                .cmd("search", quotes::QuotesSearch::new())
+                .cmd("del", quotes::QuotesDel::new())
+        })
+        .group("Server Variables", |group| {
+            perms.require("svar list", permissions::superuser);
+            perms.require("svar get", permissions::superuser);
+            perms.require("svar set", permissions::superuser);
+            perms.require("svar reset", permissions::superuser);
+            group
+                .prefix("svar")
+                .cmd("list", svar::SVarList::new())
+                .cmd("get", svar::SVarGet::new())
+                .cmd("set", svar::SVarSet::new())
+                .cmd("reset", svar::SVarReset::new())
+        })
+        .group("World of Warcraft", |mut group| {
+            perms.require("wow showme", permissions::allow_normal_if::<SVarAllowNormalShowme>);
+            perms.require("wow census", permissions::allow_normal_if::<SVarAllowNormalCensus>);
+            group = group.prefix("wow");
+            if let Some(showme) = wow::WowShowme::new(&cdata) {
+                group = group.cmd("showme", showme);
             }
-            if let Some(rm) = condenser::CondenserDelete::new(&cdata) {
-                group = group.cmd("delete", rm);
+            if let Some(census) = wow::WowCensus::new(&cdata) {
+                group = group.cmd("census", census);
             }
             group
         })
-        .group("Permissions", |group| group
-            .prefix("perm")
-            // TODO: Attach permission management commands here.
-            .cmd("set", UnimplementedCommand::new())
-        )
-        .group("World of Warcraft", |group| group
-            .prefix("wow")
-            // TODO: Attach Battle.net commands here.
-            .cmd("showme", UnimplementedCommand::new())
-            .cmd("census", UnimplementedCommand::new())
-        )
         .group("Utilities", |mut group| { // Basic utilities. Not worth splitting out into command modules alone.
             group = group
                 .command("ping", |c| c
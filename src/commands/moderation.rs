@@ -0,0 +1,276 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use regex::Regex;
+use serenity::framework::standard::{Args, Command, CommandError, CommandOptions};
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, MessageId, UserId};
+use serenity::prelude::{Context, Mentionable};
+use serenity::utils::parse_username;
+use serenity::Error as SerenityError;
+
+use constants::COLOUR_PRIMARY;
+use utils::{error_embed, usage_error_embed};
+use workers::run_on_worker;
+
+/// Maximum number of messages `!rmhist` will delete at once.
+const MAX_PURGE: usize = 1000;
+
+/// Maximum number of messages `!rmhist` will scan looking for matches.
+const MAX_SCAN: usize = 5000;
+
+/// Discord only allows bulk deletion of messages younger than 14 days. We leave a little margin for clock skew.
+const BULK_DELETE_MAX_AGE_MINUTES: i64 = 14 * 24 * 60 - 5;
+
+/// Which messages `!rmhist` should delete.
+pub struct PurgeFilter {
+    /// Most messages to delete.
+    pub count: usize,
+    pub user: Option<UserId>,
+    /// Only delete messages whose content matches this.
+    pub pattern: Option<Regex>,
+    pub bots_only: bool,
+    pub attachments_only: bool,
+    pub before: Option<MessageId>,
+    pub after: Option<MessageId>,
+}
+
+impl PurgeFilter {
+    /// Parses `!rmhist` arguments: a count followed by any of `--user`, `--regex`, `--bots`, `--attachments`,
+    /// `--before` and `--after`. Returns a user-presentable reason on failure.
+    pub fn parse(input: &str) -> Result<PurgeFilter, String> {
+        let mut tokens = input.split_whitespace();
+
+        let count = tokens
+            .next()
+            .and_then(|it| it.parse::<usize>().ok())
+            .ok_or_else(|| "You must specify how many messages to delete.".to_string())?;
+        if count == 0 || count > MAX_PURGE {
+            return Err(format!("You can delete between 1 and {} messages at once.", MAX_PURGE));
+        }
+
+        let mut filter = PurgeFilter {
+            count,
+            user: None,
+            pattern: None,
+            bots_only: false,
+            attachments_only: false,
+            before: None,
+            after: None,
+        };
+
+        while let Some(flag) = tokens.next() {
+            match flag {
+                "--bots" => filter.bots_only = true,
+                "--attachments" => filter.attachments_only = true,
+                "--user" => {
+                    let id = tokens
+                        .next()
+                        .and_then(|it| parse_username(it).or_else(|| it.parse().ok()))
+                        .ok_or_else(|| "`--user` must be followed by a user mention.".to_string())?;
+                    filter.user = Some(UserId(id));
+                }
+                "--regex" => {
+                    let pattern = tokens
+                        .next()
+                        .ok_or_else(|| "`--regex` must be followed by a pattern.".to_string())?;
+                    filter.pattern = Some(Regex::new(pattern).map_err(|err| format!("Invalid regex: {}", err))?);
+                }
+                "--before" | "--after" => {
+                    let id = tokens
+                        .next()
+                        .and_then(|it| it.parse::<u64>().ok())
+                        .ok_or_else(|| format!("`{}` must be followed by a message ID.", flag))?;
+                    if flag == "--before" {
+                        filter.before = Some(MessageId(id));
+                    } else {
+                        filter.after = Some(MessageId(id));
+                    }
+                }
+                other => return Err(format!("Unknown option `{}`.", other)),
+            }
+        }
+
+        Ok(filter)
+    }
+
+    fn matches(&self, msg: &Message) -> bool {
+        if let Some(user) = self.user {
+            if msg.author.id != user {
+                return false;
+            }
+        }
+        if self.bots_only && !msg.author.bot {
+            return false;
+        }
+        if self.attachments_only && msg.attachments.is_empty() {
+            return false;
+        }
+        if let Some(ref pattern) = self.pattern {
+            if !pattern.is_match(&msg.content) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Checks whether a message sent at `sent` is young enough to be bulk deleted at `now`.
+pub fn bulk_deletable(sent: DateTime<Utc>, now: DateTime<Utc>) -> bool {
+    sent > now - Duration::minutes(BULK_DELETE_MAX_AGE_MINUTES)
+}
+
+/// Walks back through a channel's history from `start`, collecting the IDs of messages matching the filter, split into
+/// those which can be bulk deleted and those which are too old.
+fn collect_matches(
+    channel_id: ChannelId,
+    start: MessageId,
+    filter: &PurgeFilter,
+) -> Result<(Vec<MessageId>, Vec<MessageId>), SerenityError> {
+    let now = Utc::now();
+    let mut recent = Vec::new();
+    let mut old = Vec::new();
+    let mut cursor = start;
+    let mut scanned = 0;
+
+    'scan: while scanned < MAX_SCAN {
+        let page = channel_id.messages(|g| g.before(cursor).limit(100))?;
+        if page.is_empty() {
+            break;
+        }
+
+        for msg in &page {
+            scanned += 1;
+            cursor = msg.id;
+
+            if let Some(after) = filter.after {
+                if msg.id.0 <= after.0 {
+                    break 'scan;
+                }
+            }
+            if !filter.matches(msg) {
+                continue;
+            }
+
+            if bulk_deletable(msg.timestamp.with_timezone(&Utc), now) {
+                recent.push(msg.id);
+            } else {
+                old.push(msg.id);
+            }
+            if recent.len() + old.len() >= filter.count {
+                break 'scan;
+            }
+        }
+    }
+
+    Ok((recent, old))
+}
+
+/// Deletes messages, bulk deleting where possible. Returns the number of messages deleted.
+fn delete_messages(channel_id: ChannelId, recent: &[MessageId], old: &[MessageId]) -> usize {
+    let mut deleted = 0;
+
+    for chunk in recent.chunks(100) {
+        // Bulk deletion requires at least two messages.
+        let res = if chunk.len() == 1 {
+            channel_id.delete_message(chunk[0])
+        } else {
+            channel_id.delete_messages(chunk)
+        };
+
+        match res {
+            Ok(_) => deleted += chunk.len(),
+            Err(err) => warn!("Bulk delete in channel {} failed: {:?}", channel_id, err),
+        }
+    }
+
+    // Messages older than 14 days have to be deleted one at a time.
+    for id in old {
+        match channel_id.delete_message(*id) {
+            Ok(_) => deleted += 1,
+            Err(err) => warn!("Delete of message {} in channel {} failed: {:?}", id, channel_id, err),
+        }
+    }
+
+    deleted
+}
+
+/// Serenity command for bulk deleting channel history.
+pub struct RmHist {
+    opts: Arc<CommandOptions>,
+}
+
+impl RmHist {
+    pub fn new() -> RmHist {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(format!(
+            "Delete up to {} recent messages in this channel. Filters: `--user @USER`, `--regex PATTERN` (no spaces), \
+             `--bots`, `--attachments`, `--before MESSAGE_ID`, `--after MESSAGE_ID`.",
+            MAX_PURGE
+        ));
+        opts.usage = Some("COUNT [FILTERS...]".into());
+        opts.example = Some("50 --user @Spammer --attachments".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+
+        RmHist {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for RmHist {
+    fn execute(&self, _ctx: &mut Context, msg: &Message, args: Args) -> Result<(), CommandError> {
+        let filter = match PurgeFilter::parse(args.full()) {
+            Ok(filter) => filter,
+            Err(err) => {
+                usage_error_embed("rmhist", &err, Arc::clone(&self.opts), msg);
+                return Ok(());
+            }
+        };
+
+        // Gather everything the closure will need here.
+        let usr_mention = msg.author.mention();
+        let usr_tag = msg.author.tag();
+        let channel_id = msg.channel_id;
+        let start = filter.before.unwrap_or(msg.id);
+
+        run_on_worker(move || {
+            let (recent, old) = match collect_matches(channel_id, start, &filter) {
+                Ok(found) => found,
+                Err(err) => {
+                    warn!("Unable to fetch history for channel {}: {:?}", channel_id, err);
+                    error_embed(
+                        &channel_id,
+                        "Unable to read this channel's history. Check the bot's permissions.",
+                        Some(&usr_mention),
+                        |e| e,
+                    );
+                    return;
+                }
+            };
+
+            let found = recent.len() + old.len();
+            let deleted = delete_messages(channel_id, &recent, &old);
+            info!("{} deleted {}/{} messages in channel {}", usr_tag, deleted, found, channel_id);
+
+            let _ = channel_id.send_message(|m| {
+                m.content(usr_mention).embed(|mut e| {
+                    e = e.title("History Deleted")
+                        .colour(*COLOUR_PRIMARY)
+                        .description(format!("Deleted {} of {} matching messages.", deleted, found));
+                    if deleted < found {
+                        e = e.field("Note", "Some messages couldn't be deleted. Check the bot's permissions.", false);
+                    }
+                    e
+                })
+            });
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
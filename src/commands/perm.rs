@@ -0,0 +1,243 @@
+use std::sync::Arc;
+
+use serenity::framework::standard::{Args, Command, CommandError, CommandOptions};
+use serenity::model::channel::Message;
+use serenity::model::id::{GuildId, RoleId, UserId};
+use serenity::prelude::{Context, Mentionable};
+use serenity::utils::{parse_role, parse_username};
+
+use constants::COLOUR_PRIMARY;
+use server::permissions::{Grantee, Permissions};
+use types::PermissionsMarker;
+use utils::{error_embed, require_guild, usage_error_embed};
+
+fn parse_grantee(arg: &str) -> Option<Grantee> {
+    if let Some(id) = parse_role(arg) {
+        Some(Grantee::Role(RoleId(id)))
+    } else if let Some(id) = parse_username(arg) {
+        Some(Grantee::User(UserId(id)))
+    } else {
+        None
+    }
+}
+
+fn mention(grantee: Grantee) -> String {
+    match grantee {
+        Grantee::User(id) => id.mention(),
+        Grantee::Role(id) => id.mention(),
+    }
+}
+
+/// Checks that the message's author may change superuser grants, i.e. is the guild owner or a bot owner. Superusers
+/// can't, as they could otherwise promote anyone or demote each other.
+fn require_grant_rights(perms: &Permissions, msg: &Message, guild_id: GuildId) -> bool {
+    let guild_owner = guild_id.find().map_or(false, |it| it.read().owner_id == msg.author.id);
+    if !guild_owner && !perms.is_owner(msg.author.id) {
+        error_embed(&msg.channel_id, "Only the server owner can change superusers.", None, |e| e);
+        return false;
+    }
+    true
+}
+
+/// Pulls a user or role mention out of the arguments, reporting errors as usage embeds.
+fn require_grantee(cmd_name: &str, opts: &Arc<CommandOptions>, msg: &Message, args: &mut Args) -> Option<Grantee> {
+    let grantee = args.single::<String>().ok().and_then(|it| parse_grantee(&it));
+    if grantee.is_none() {
+        usage_error_embed(cmd_name, "You must mention a user or role.", Arc::clone(opts), msg);
+    }
+    grantee
+}
+
+/// Grants superuser on the current guild to a user or role.
+pub struct PermSet {
+    opts: Arc<CommandOptions>,
+}
+
+impl PermSet {
+    pub fn new() -> PermSet {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Grant superuser on this server to a user or role. Only the server owner can do this.".into());
+        opts.usage = Some("@USER|@ROLE".into());
+        opts.example = Some("@Moderators".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+        opts.max_args = Some(1);
+
+        PermSet {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for PermSet {
+    fn execute(&self, ctx: &mut Context, msg: &Message, mut args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let grantee = match require_grantee("perm set", &self.opts, msg, &mut args) {
+            Some(grantee) => grantee,
+            None => return Ok(()),
+        };
+
+        let perms = perms!(ctx.data);
+        if !require_grant_rights(&perms, msg, guild_id) {
+            return Ok(());
+        }
+
+        // The @everyone role shares the guild's ID.
+        if grantee == Grantee::Role(RoleId(guild_id.0)) {
+            error_embed(&msg.channel_id, "Superuser can't be granted to @everyone.", None, |e| e);
+            return Ok(());
+        }
+        match perms.grant(guild_id, grantee) {
+            Ok(true) => {
+                info!("Superuser granted to {:?} in guild {} by {}", grantee, guild_id, msg.author.tag());
+                let _ = msg.channel_id.send_message(|m| {
+                    m.embed(|e| {
+                        e.title("Superuser Granted")
+                            .colour(*COLOUR_PRIMARY)
+                            .description(format!("{} is now a superuser on this server.", mention(grantee)))
+                    })
+                });
+            }
+            Ok(false) => {
+                error_embed(&msg.channel_id, "That user or role is already a superuser.", None, |e| e);
+            }
+            Err(err) => {
+                error!("Unable to save permissions for guild {}: {}", guild_id, err);
+                error_embed(&msg.channel_id, "Unable to save permissions. Ask your admin for assistance.", None, |e| e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Revokes superuser on the current guild from a user or role.
+pub struct PermRemove {
+    opts: Arc<CommandOptions>,
+}
+
+impl PermRemove {
+    pub fn new() -> PermRemove {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(
+            "Revoke superuser on this server from a user or role. Only the server owner can do this.".into(),
+        );
+        opts.usage = Some("@USER|@ROLE".into());
+        opts.example = Some("@Moderators".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+        opts.max_args = Some(1);
+
+        PermRemove {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for PermRemove {
+    fn execute(&self, ctx: &mut Context, msg: &Message, mut args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let grantee = match require_grantee("perm remove", &self.opts, msg, &mut args) {
+            Some(grantee) => grantee,
+            None => return Ok(()),
+        };
+
+        let perms = perms!(ctx.data);
+        if !require_grant_rights(&perms, msg, guild_id) {
+            return Ok(());
+        }
+        match perms.revoke(guild_id, grantee) {
+            Ok(true) => {
+                info!("Superuser revoked from {:?} in guild {} by {}", grantee, guild_id, msg.author.tag());
+                let _ = msg.channel_id.send_message(|m| {
+                    m.embed(|e| {
+                        e.title("Superuser Revoked")
+                            .colour(*COLOUR_PRIMARY)
+                            .description(format!("{} is no longer a superuser on this server.", mention(grantee)))
+                    })
+                });
+            }
+            Ok(false) => {
+                error_embed(&msg.channel_id, "That user or role is not a superuser.", None, |e| e);
+            }
+            Err(err) => {
+                error!("Unable to save permissions for guild {}: {}", guild_id, err);
+                error_embed(&msg.channel_id, "Unable to save permissions. Ask your admin for assistance.", None, |e| e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Lists superuser grants on the current guild.
+pub struct PermList {
+    opts: Arc<CommandOptions>,
+}
+
+impl PermList {
+    pub fn new() -> PermList {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("List the users and roles with superuser on this server.".into());
+        opts.guild_only = true;
+        opts.max_args = Some(0);
+
+        PermList {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for PermList {
+    fn execute(&self, ctx: &mut Context, msg: &Message, _: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let perms = perms!(ctx.data);
+        let grants = perms.grants(guild_id);
+        let render = |list: Vec<String>| {
+            if list.is_empty() {
+                "None".to_string()
+            } else {
+                list.join("\n")
+            }
+        };
+
+        let users = render(grants.users.iter().map(|id| mention(Grantee::User(UserId(*id)))).collect());
+        let roles = render(grants.roles.iter().map(|id| mention(Grantee::Role(RoleId(*id)))).collect());
+        let owners = render(perms.owners().iter().map(|id| mention(Grantee::User(*id))).collect());
+
+        let _ = msg.channel_id.send_message(|m| {
+            m.embed(|e| {
+                e.title("Superusers")
+                    .colour(*COLOUR_PRIMARY)
+                    .description("The server owner is always a superuser. Bot owners have every permission.")
+                    .field("Users", users, true)
+                    .field("Roles", roles, true)
+                    .field("Bot Owners", owners, true)
+            })
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
@@ -0,0 +1,408 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use fuzzy_match::fuzzy_match;
+use rand::{self, Rng};
+use serenity::framework::standard::{Args, Command, CommandError, CommandOptions};
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, MessageId, UserId};
+use serenity::prelude::{Context, Mentionable};
+use serenity::utils::parse_username;
+
+use constants::{COLOUR_QUOTES, EMBED_TOTAL_LIMIT};
+use types::GuildConfigMarker;
+use utils::{error_embed, require_guild, truncate, usage_error_embed};
+
+/// Name of the quotes section in each guild's configuration.
+const SECTION: &str = "quotes";
+
+/// Maximum number of matches to show for a search.
+const MAX_SEARCH_RESULTS: usize = 5;
+
+/// Maximum length of each quote shown in search results.
+const SEARCH_PREVIEW_LENGTH: usize = 300;
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+struct QuoteBook {
+    /// Number to assign to the next quote. Numbers are never reused, so deleting a quote doesn't renumber others.
+    #[serde(default)]
+    next_id: u64,
+    #[serde(default)]
+    quotes: Vec<Quote>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+struct Quote {
+    id: u64,
+    text: String,
+    /// Display name of whoever said it, if known.
+    author: Option<String>,
+    author_id: Option<u64>,
+    added_by: u64,
+    time: DateTime<Utc>,
+}
+
+fn send_quote(channel_id: ChannelId, quote: &Quote) {
+    let _ = channel_id.send_message(|m| {
+        m.embed(|mut e| {
+            e = e.title(format!("Quote #{}", quote.id))
+                .colour(*COLOUR_QUOTES)
+                .description(&quote.text);
+
+            let author = match (quote.author_id, &quote.author) {
+                (Some(id), _) => Some(UserId(id).mention()),
+                (None, &Some(ref name)) => Some(name.clone()),
+                (None, &None) => None,
+            };
+            if let Some(author) = author {
+                e = e.field("Author", author, true);
+            }
+
+            e.field("Date", quote.time.format("%d/%m/%Y at %H:%M:%S (%Z)"), true)
+                .field("Added By", UserId(quote.added_by).mention(), true)
+        })
+    });
+}
+
+/// Parses a quote number argument, reporting errors as usage embeds.
+fn require_number(cmd_name: &str, opts: &Arc<CommandOptions>, msg: &Message, args: &mut Args) -> Option<u64> {
+    let number = args.single::<u64>().ok();
+    if number.is_none() {
+        usage_error_embed(cmd_name, "You must specify a quote number.", Arc::clone(opts), msg);
+    }
+    number
+}
+
+/// Saves a quote, either from text or from an existing message.
+pub struct QuotesAdd {
+    opts: Arc<CommandOptions>,
+}
+
+impl QuotesAdd {
+    pub fn new() -> QuotesAdd {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(
+            "Save a quote. Give either the ID of a message in this channel, or the quote text (optionally starting with \
+             a mention of who said it)."
+                .into(),
+        );
+        opts.usage = Some("MESSAGE_ID | [@USER] TEXT".into());
+        opts.example = Some("@Arkan It's not a bug, it's a feature.".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+
+        QuotesAdd {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for QuotesAdd {
+    fn execute(&self, ctx: &mut Context, msg: &Message, args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let full = args.full().trim().to_string();
+        let (text, author, author_id, time) = if let Ok(id) = full.parse::<u64>() {
+            let quoted = match msg.channel_id.message(MessageId(id)) {
+                Ok(quoted) => quoted,
+                Err(_) => {
+                    usage_error_embed(
+                        "quotes add",
+                        "No message with that ID in this channel.",
+                        Arc::clone(&self.opts),
+                        msg,
+                    );
+                    return Ok(());
+                }
+            };
+            (
+                quoted.content_safe(),
+                Some(quoted.author.name.clone()),
+                Some(quoted.author.id.0),
+                quoted.timestamp.with_timezone(&Utc),
+            )
+        } else {
+            let mut parts = full.splitn(2, char::is_whitespace);
+            let first = parts.next().unwrap_or("");
+            match parse_username(first) {
+                Some(id) => {
+                    let name = UserId(id).get().map(|it| it.name).ok();
+                    (parts.next().unwrap_or("").trim().to_string(), name, Some(id), Utc::now())
+                }
+                None => (full.clone(), None, None, Utc::now()),
+            }
+        };
+
+        if text.is_empty() {
+            usage_error_embed("quotes add", "The quote is empty.", Arc::clone(&self.opts), msg);
+            return Ok(());
+        }
+
+        let added_by = msg.author.id.0;
+        let store = guild_conf!(ctx.data);
+        let res = store.update_section(guild_id, SECTION, |book: &mut QuoteBook| {
+            book.next_id += 1;
+            let quote = Quote {
+                id: book.next_id,
+                text,
+                author,
+                author_id,
+                added_by,
+                time,
+            };
+            book.quotes.push(quote.clone());
+            quote
+        });
+
+        match res {
+            Ok(quote) => send_quote(msg.channel_id, &quote),
+            Err(err) => {
+                error!("Unable to save quote for guild {}: {}", guild_id, err);
+                error_embed(&msg.channel_id, "Unable to save quote. Ask your admin for assistance.", None, |e| e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Recalls a quote by number.
+pub struct QuotesGet {
+    opts: Arc<CommandOptions>,
+}
+
+impl QuotesGet {
+    pub fn new() -> QuotesGet {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Recall a quote by number.".into());
+        opts.usage = Some("NUMBER".into());
+        opts.example = Some("42".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+        opts.max_args = Some(1);
+
+        QuotesGet {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for QuotesGet {
+    fn execute(&self, ctx: &mut Context, msg: &Message, mut args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let number = match require_number("quotes get", &self.opts, msg, &mut args) {
+            Some(number) => number,
+            None => return Ok(()),
+        };
+
+        let store = guild_conf!(ctx.data);
+        let book: QuoteBook = store.get_section(guild_id, SECTION);
+        match book.quotes.iter().find(|it| it.id == number) {
+            Some(quote) => send_quote(msg.channel_id, quote),
+            None => error_embed(&msg.channel_id, &format!("There is no quote #{}.", number), None, |e| e),
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Recalls a random quote.
+pub struct QuotesRandom {
+    opts: Arc<CommandOptions>,
+}
+
+impl QuotesRandom {
+    pub fn new() -> QuotesRandom {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Recall a random quote.".into());
+        opts.guild_only = true;
+        opts.max_args = Some(0);
+
+        QuotesRandom {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for QuotesRandom {
+    fn execute(&self, ctx: &mut Context, msg: &Message, _: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let store = guild_conf!(ctx.data);
+        let book: QuoteBook = store.get_section(guild_id, SECTION);
+        match rand::thread_rng().choose(&book.quotes) {
+            Some(quote) => send_quote(msg.channel_id, quote),
+            None => error_embed(&msg.channel_id, "No quotes have been saved yet.", None, |e| e),
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Searches quotes, by substring first and falling back to the closest fuzzy match.
+pub struct QuotesSearch {
+    opts: Arc<CommandOptions>,
+}
+
+impl QuotesSearch {
+    pub fn new() -> QuotesSearch {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Search for quotes containing some text, or the closest match if none do.".into());
+        opts.usage = Some("TEXT".into());
+        opts.example = Some("feature".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+
+        QuotesSearch {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for QuotesSearch {
+    fn execute(&self, ctx: &mut Context, msg: &Message, args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+
+        let needle = args.full().trim().to_lowercase();
+        if needle.is_empty() {
+            usage_error_embed("quotes search", "No search text specified.", Arc::clone(&self.opts), msg);
+            return Ok(());
+        }
+
+        let store = guild_conf!(ctx.data);
+        let book: QuoteBook = store.get_section(guild_id, SECTION);
+
+        let matches = book.quotes
+            .iter()
+            .filter(|it| it.text.to_lowercase().contains(&needle))
+            .collect::<Vec<_>>();
+
+        if matches.len() == 1 {
+            send_quote(msg.channel_id, matches[0]);
+        } else if !matches.is_empty() {
+            let _ = msg.channel_id.send_message(|m| {
+                m.embed(|mut e| {
+                    let title = format!("{} matching quotes", matches.len());
+                    let description = "Use `!quotes get NUMBER` to see a quote in full.";
+                    let mut remaining = EMBED_TOTAL_LIMIT - title.chars().count() - description.chars().count();
+                    e = e.title(title).colour(*COLOUR_QUOTES).description(description);
+
+                    for quote in matches.iter().take(MAX_SEARCH_RESULTS) {
+                        let name = format!("#{}", quote.id);
+                        let text = truncate(&quote.text, SEARCH_PREVIEW_LENGTH);
+                        let length = name.chars().count() + text.chars().count();
+                        if length > remaining {
+                            break;
+                        }
+                        remaining -= length;
+                        e = e.field(name, text, false);
+                    }
+                    e
+                })
+            });
+        } else {
+            let closest = fuzzy_match(&needle, book.quotes.iter().map(|it| (it.text.as_str(), it)));
+            match closest {
+                Some(quote) => send_quote(msg.channel_id, quote),
+                None => error_embed(&msg.channel_id, "No matching quotes.", None, |e| e),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Deletes a quote by number.
+pub struct QuotesDel {
+    opts: Arc<CommandOptions>,
+}
+
+impl QuotesDel {
+    pub fn new() -> QuotesDel {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Delete a quote by number.".into());
+        opts.usage = Some("NUMBER".into());
+        opts.example = Some("42".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+        opts.max_args = Some(1);
+
+        QuotesDel {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for QuotesDel {
+    fn execute(&self, ctx: &mut Context, msg: &Message, mut args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let number = match require_number("quotes del", &self.opts, msg, &mut args) {
+            Some(number) => number,
+            None => return Ok(()),
+        };
+
+        let store = guild_conf!(ctx.data);
+        let res = store.update_section(guild_id, SECTION, |book: &mut QuoteBook| {
+            let len = book.quotes.len();
+            book.quotes.retain(|it| it.id != number);
+            book.quotes.len() != len
+        });
+
+        match res {
+            Ok(true) => {
+                info!("Quote #{} deleted in guild {} by {}", number, guild_id, msg.author.tag());
+                let _ = msg.channel_id.send_message(|m| {
+                    m.embed(|e| {
+                        e.title("Quote Deleted")
+                            .colour(*COLOUR_QUOTES)
+                            .description(format!("Quote #{} has been deleted.", number))
+                    })
+                });
+            }
+            Ok(false) => {
+                error_embed(&msg.channel_id, &format!("There is no quote #{}.", number), None, |e| e);
+            }
+            Err(err) => {
+                error!("Unable to save quotes for guild {}: {}", guild_id, err);
+                error_embed(&msg.channel_id, "Unable to save quotes. Ask your admin for assistance.", None, |e| e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
@@ -0,0 +1,64 @@
+//! Glue between Serenity events and the snark engine.
+use std::sync::Arc;
+use std::time::Instant;
+
+use serenity::model::channel::Message;
+use serenity::model::id::GuildId;
+use serenity::prelude::Mutex;
+use serenity::CACHE;
+use typemap::ShareMap;
+
+use server::svar::SVarUseSnark;
+use snark::SnarkEngine;
+use types::{GuildConfigMarker, SnarkMarker};
+
+/// Returns the snark engine if the message's guild has snark enabled. Never snarks in DMs or at other bots.
+fn engine_for(data: &Arc<Mutex<ShareMap>>, msg: &Message) -> Option<(GuildId, Arc<SnarkEngine>)> {
+    if msg.author.bot {
+        return None;
+    }
+    let guild_id = msg.guild_id()?;
+    if !guild_conf!(data).get::<SVarUseSnark>(guild_id) {
+        return None;
+    }
+
+    let lock = data.lock();
+    let engine = lock.get::<SnarkMarker>().expect("unable to load snark engine");
+    Some((guild_id, Arc::clone(engine)))
+}
+
+/// Responds to ordinary (non-command) messages which match a trigger.
+pub fn on_message(data: &Arc<Mutex<ShareMap>>, msg: &Message) {
+    if msg.content.starts_with('!') {
+        return;
+    }
+    let (guild_id, engine) = match engine_for(data, msg) {
+        Some(found) => found,
+        None => return,
+    };
+
+    let bot_id = CACHE.read().user.id;
+    let mentions_bot = msg.mentions.iter().any(|it| it.id == bot_id);
+    if let Some(response) = engine.message(guild_id, &msg.content, mentions_bot, Instant::now()) {
+        let _ = msg.channel_id.say(response);
+    }
+}
+
+/// Tracks a command which failed to run, e.g. because it doesn't exist or the user lacked permission.
+pub fn command_failed(data: &Arc<Mutex<ShareMap>>, msg: &Message) {
+    let (guild_id, engine) = match engine_for(data, msg) {
+        Some(found) => found,
+        None => return,
+    };
+
+    if let Some(response) = engine.command_failed(guild_id, msg.author.id, Instant::now()) {
+        let _ = msg.channel_id.say(response);
+    }
+}
+
+/// Tracks a command which ran successfully.
+pub fn command_succeeded(data: &Arc<Mutex<ShareMap>>, msg: &Message) {
+    if let Some((guild_id, engine)) = engine_for(data, msg) {
+        engine.command_succeeded(guild_id, msg.author.id);
+    }
+}
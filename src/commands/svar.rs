@@ -0,0 +1,327 @@
+use std::sync::Arc;
+
+use serde_json::Value;
+use serenity::framework::standard::{Args, Command, CommandError, CommandOptions};
+use serenity::model::channel::Message;
+use serenity::prelude::Context;
+
+use constants::COLOUR_PRIMARY;
+use server::config::ConfigError;
+use server::svar::{self, SVarInfo, SVARS};
+use types::{GuildConfigMarker, PermissionsMarker};
+use utils::{error_embed, require_guild, usage_error_embed};
+
+/// Renders a stored SVar value for display, without the quotes JSON would put around strings.
+fn display_value(value: &Value) -> String {
+    match *value {
+        Value::String(ref s) => s.clone(),
+        ref other => other.to_string(),
+    }
+}
+
+/// Pulls an SVar key out of the arguments and looks it up in the registry, reporting errors as usage embeds.
+fn require_svar(
+    cmd_name: &str,
+    opts: &Arc<CommandOptions>,
+    msg: &Message,
+    args: &mut Args,
+) -> Option<&'static SVarInfo> {
+    let key = match args.single::<String>() {
+        Ok(key) => key,
+        Err(_) => {
+            usage_error_embed(cmd_name, "No SVar key specified.", Arc::clone(opts), msg);
+            return None;
+        }
+    };
+
+    let info = svar::find(&key);
+    if info.is_none() {
+        usage_error_embed(
+            cmd_name,
+            &format!("Unknown SVar `{}`. Use `!svar list` to see all SVars.", key),
+            Arc::clone(opts),
+            msg,
+        );
+    }
+    info
+}
+
+/// Checks that the author of a message may change an SVar, reporting an error if not.
+fn require_level(ctx: &Context, msg: &Message, info: &SVarInfo) -> bool {
+    if perms!(ctx.data).level(msg.author.id, msg.guild_id()) < info.level {
+        error_embed(
+            &msg.channel_id,
+            &format!("Only a {} can change `{}`.", info.level, info.key),
+            None,
+            |e| e,
+        );
+        return false;
+    }
+    true
+}
+
+fn send_svar_embed(msg: &Message, title: &str, info: &SVarInfo, value: &Value) {
+    let _ = msg.channel_id.send_message(|m| {
+        m.embed(|mut e| {
+            e = e.title(title)
+                .colour(*COLOUR_PRIMARY)
+                .description(info.human)
+                .field("Key", format!("`{}`", info.key), true)
+                .field("Type", format!("`{}`", info.type_name), true)
+                .field("Value", format!("`{}`", display_value(value)), true)
+                .field("Default", format!("`{}`", display_value(&(info.default)())), true);
+
+            if !info.constraints.is_empty() {
+                let constraints = info.constraints
+                    .iter()
+                    .map(|it| it.describe())
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                e = e.field("Constraints", constraints, false);
+            }
+
+            e
+        })
+    });
+}
+
+/// Lists every SVar along with its value in the current guild.
+pub struct SVarList {
+    opts: Arc<CommandOptions>,
+}
+
+impl SVarList {
+    pub fn new() -> SVarList {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("List all server variables and their values on this server.".into());
+        opts.guild_only = true;
+        opts.max_args = Some(0);
+
+        SVarList {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for SVarList {
+    fn execute(&self, ctx: &mut Context, msg: &Message, _: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let store = guild_conf!(ctx.data);
+
+        let _ = msg.channel_id.send_message(|m| {
+            m.embed(|mut e| {
+                e = e.title("Server Variables").colour(*COLOUR_PRIMARY).description(
+                    "Use `!svar set KEY VALUE` to change a value, or `!svar reset KEY` to restore its default.",
+                );
+
+                for info in SVARS.iter() {
+                    let value = store.get_raw(guild_id, info.key);
+                    let is_default = value.is_none();
+                    let value = value.unwrap_or_else(info.default);
+                    e = e.field(
+                        info.human,
+                        format!(
+                            "`{}` = `{}`{}",
+                            info.key,
+                            display_value(&value),
+                            if is_default { " (default)" } else { "" }
+                        ),
+                        false,
+                    );
+                }
+
+                e
+            })
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Shows a single SVar in detail.
+pub struct SVarGet {
+    opts: Arc<CommandOptions>,
+}
+
+impl SVarGet {
+    pub fn new() -> SVarGet {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Show the value of a server variable on this server.".into());
+        opts.usage = Some("KEY".into());
+        opts.example = Some("roll_max".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+        opts.max_args = Some(1);
+
+        SVarGet {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for SVarGet {
+    fn execute(&self, ctx: &mut Context, msg: &Message, mut args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let info = match require_svar("svar get", &self.opts, msg, &mut args) {
+            Some(info) => info,
+            None => return Ok(()),
+        };
+
+        let store = guild_conf!(ctx.data);
+        let value = store.get_raw(guild_id, info.key).unwrap_or_else(info.default);
+        send_svar_embed(msg, "Server Variable", info, &value);
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Sets an SVar from a string, parsed to the SVar's underlying type.
+pub struct SVarSet {
+    opts: Arc<CommandOptions>,
+}
+
+impl SVarSet {
+    pub fn new() -> SVarSet {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Set the value of a server variable on this server.".into());
+        opts.usage = Some("KEY VALUE".into());
+        opts.example = Some("roll_max 20".into());
+        opts.guild_only = true;
+        opts.min_args = Some(2);
+
+        SVarSet {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for SVarSet {
+    fn execute(&self, ctx: &mut Context, msg: &Message, mut args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let info = match require_svar("svar set", &self.opts, msg, &mut args) {
+            Some(info) => info,
+            None => return Ok(()),
+        };
+        if !require_level(ctx, msg, info) {
+            return Ok(());
+        }
+
+        let value = match (info.parse)(args.rest()) {
+            Ok(value) => value,
+            Err(err) => {
+                usage_error_embed(
+                    "svar set",
+                    &format!("Invalid value for `{}` (expected `{}`): {}", info.key, info.type_name, err),
+                    Arc::clone(&self.opts),
+                    msg,
+                );
+                return Ok(());
+            }
+        };
+
+        let store = guild_conf!(ctx.data);
+        match store.set_raw(guild_id, info.key, value.clone()) {
+            Ok(_) => {}
+            Err(ConfigError::Invalid(reason)) => {
+                usage_error_embed("svar set", &reason, Arc::clone(&self.opts), msg);
+                return Ok(());
+            }
+            Err(err) => {
+                error!("Unable to save SVar '{}' for guild {}: {}", info.key, guild_id, err);
+                error_embed(&msg.channel_id, "Unable to save the new value. Ask your admin for assistance.", None, |e| e);
+                return Ok(());
+            }
+        }
+
+        info!("SVar '{}' set to {} in guild {} by {}", info.key, value, guild_id, msg.author.tag());
+        send_svar_embed(msg, "Server Variable Updated", info, &value);
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Resets an SVar to its default value.
+pub struct SVarReset {
+    opts: Arc<CommandOptions>,
+}
+
+impl SVarReset {
+    pub fn new() -> SVarReset {
+        let mut opts = CommandOptions::default();
+        opts.desc = Some("Reset a server variable on this server to its default value.".into());
+        opts.usage = Some("KEY".into());
+        opts.example = Some("roll_max".into());
+        opts.guild_only = true;
+        opts.min_args = Some(1);
+        opts.max_args = Some(1);
+
+        SVarReset {
+            opts: Arc::new(opts),
+        }
+    }
+}
+
+impl Command for SVarReset {
+    fn execute(&self, ctx: &mut Context, msg: &Message, mut args: Args) -> Result<(), CommandError> {
+        let guild_id = match require_guild(msg) {
+            Some(id) => id,
+            None => return Ok(()),
+        };
+        let info = match require_svar("svar reset", &self.opts, msg, &mut args) {
+            Some(info) => info,
+            None => return Ok(()),
+        };
+        if !require_level(ctx, msg, info) {
+            return Ok(());
+        }
+
+        let store = guild_conf!(ctx.data);
+        match store.reset_raw(guild_id, info.key) {
+            Ok(_) => {}
+            Err(ConfigError::Invalid(reason)) => {
+                usage_error_embed(
+                    "svar reset",
+                    &format!("The default value is not valid with this server's other settings. {}", reason),
+                    Arc::clone(&self.opts),
+                    msg,
+                );
+                return Ok(());
+            }
+            Err(err) => {
+                error!("Unable to reset SVar '{}' for guild {}: {}", info.key, guild_id, err);
+                error_embed(&msg.channel_id, "Unable to save the new value. Ask your admin for assistance.", None, |e| e);
+                return Ok(());
+            }
+        }
+
+        info!("SVar '{}' reset in guild {} by {}", info.key, guild_id, msg.author.tag());
+        send_svar_embed(msg, "Server Variable Reset", info, &(info.default)());
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
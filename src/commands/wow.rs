@@ -0,0 +1,277 @@
+use std::sync::Arc;
+
+use serenity::framework::standard::{Args, Command, CommandError, CommandOptions};
+use serenity::model::channel::Message;
+use serenity::model::id::ChannelId;
+use serenity::prelude::{Context, Mentionable, Mutex};
+use typemap::ShareMap;
+
+use battlenet::{self, BattleNetClient, BattleNetError, REGIONS};
+use constants::*;
+use types::ConfigMarker;
+use utils::{error_embed, usage_error_embed};
+use workers::run_on_worker;
+
+/// Builds a Battle.net client from configuration, returning the client and the default region.
+fn client_from_conf(client_data: &Arc<Mutex<ShareMap>>) -> Option<(BattleNetClient, String)> {
+    let conf = conf!(client_data);
+    let token = conf.get_str(CONF_BNET_TOKEN).ok()?;
+    let base = conf.get_str(CONF_BNET_API).ok()?;
+    let region = conf.get_str(CONF_BNET_REGION).ok()?.to_lowercase();
+    Some((BattleNetClient::new(&base, &token), region))
+}
+
+/// Splits `NAME REALM... [REGION]` arguments. Realms may contain spaces, so everything between the name and an
+/// optional trailing region is treated as the realm.
+fn split_target(args: &Args, default_region: &str) -> Option<(String, String, String)> {
+    let mut parts = args.full().split_whitespace().collect::<Vec<_>>();
+    if parts.len() < 2 {
+        return None;
+    }
+
+    let last = parts[parts.len() - 1].to_lowercase();
+    let region = if parts.len() > 2 && REGIONS.iter().any(|it| *it == last) {
+        parts.pop();
+        last
+    } else {
+        default_region.to_string()
+    };
+    let name = parts.remove(0).to_string();
+    let realm = parts.join(" ");
+
+    Some((name, realm, region))
+}
+
+/// Splits `GUILD @ REALM [REGION]` arguments. Both guild and realm names may contain spaces.
+fn split_guild_target(args: &Args, default_region: &str) -> Option<(String, String, String)> {
+    let full = args.full();
+    let mut halves = full.splitn(2, '@');
+    let guild = halves.next()?.trim().to_string();
+    let mut realm_parts = halves.next()?.split_whitespace().collect::<Vec<_>>();
+    if guild.is_empty() || realm_parts.is_empty() {
+        return None;
+    }
+
+    let last = realm_parts[realm_parts.len() - 1].to_lowercase();
+    let region = if realm_parts.len() > 1 && REGIONS.iter().any(|it| *it == last) {
+        realm_parts.pop();
+        last
+    } else {
+        default_region.to_string()
+    };
+
+    Some((guild, realm_parts.join(" "), region))
+}
+
+/// Renders one census breakdown as lines of `label: count (percent)`.
+fn render_breakdown<K, F: Fn(&K) -> String>(counts: &[(K, usize)], total: usize, label: F) -> String {
+    counts
+        .iter()
+        .map(|&(ref key, count)| {
+            format!("{}: {} ({:.0}%)", label(key), count, count as f64 * 100.0 / total as f64)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Reports a Battle.net error to the user.
+fn handle_bnet_err(err: BattleNetError, channel_id: ChannelId, usr_mention: &str, not_found: &str) {
+    let text = match err {
+        BattleNetError::NotFound => not_found.to_string(),
+        BattleNetError::Unauthorized => "The bot's Battle.net API key is invalid. Ask your admin for assistance.".into(),
+        BattleNetError::InvalidRequest => "Invalid name or realm.".into(),
+        err => {
+            warn!("Error communicating with Battle.net: {:?}", err);
+            "An error occurred when communicating with Battle.net. Try again later.".into()
+        }
+    };
+    error_embed(&channel_id, &text, Some(usr_mention), |e| e);
+}
+
+/// Serenity command for looking up a character's profile on Battle.net.
+pub struct WowShowme {
+    opts: Arc<CommandOptions>,
+    client: BattleNetClient,
+    region: String,
+}
+
+impl WowShowme {
+    pub fn new(client_data: &Arc<Mutex<ShareMap>>) -> Option<WowShowme> {
+        let (client, region) = client_from_conf(client_data)?;
+
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(format!(
+            "Show a World of Warcraft character's profile. The region defaults to `{}`.",
+            region
+        ));
+        opts.usage = Some("NAME REALM [REGION]".into());
+        opts.example = Some("Arkan Argent Dawn eu".into());
+        opts.min_args = Some(2);
+
+        Some(WowShowme {
+            opts: Arc::new(opts),
+            client,
+            region,
+        })
+    }
+}
+
+impl Command for WowShowme {
+    fn execute(&self, _ctx: &mut Context, msg: &Message, args: Args) -> Result<(), CommandError> {
+        let (name, realm, region) = match split_target(&args, &self.region) {
+            Some(target) => target,
+            None => {
+                usage_error_embed(
+                    "wow showme",
+                    "You must specify a character name and realm.",
+                    Arc::clone(&self.opts),
+                    msg,
+                );
+                return Ok(());
+            }
+        };
+
+        // Gather everything the closure will need here.
+        let usr_mention = msg.author.mention();
+        let channel_id = msg.channel_id;
+        let client = self.client.clone();
+
+        run_on_worker(move || {
+            let profile = match client.character(&region, &realm, &name) {
+                Ok(profile) => profile,
+                Err(err) => {
+                    handle_bnet_err(err, channel_id, &usr_mention, "Character not found.");
+                    return;
+                }
+            };
+
+            let armory = battlenet::armory_url(&region, &profile.realm, &profile.name);
+            let _ = channel_id.send_message(|m| {
+                m.content(usr_mention).embed(|mut e| {
+                    e = e.title(format!("{} @ {} ({})", profile.name, profile.realm, region.to_uppercase()))
+                        .url(&armory)
+                        .colour(*COLOUR_BATTLENET)
+                        .field("Level", profile.level, true)
+                        .field("Class", battlenet::class_name(profile.class), true);
+
+                    if let Some(spec) = profile.active_spec() {
+                        e = e.field("Specialisation", format!("{} ({})", spec.name, spec.role), true);
+                    }
+                    if let Some(ref items) = profile.items {
+                        e = e.field(
+                            "Item Level",
+                            format!("{} equipped ({} overall)", items.average_item_level_equipped, items.average_item_level),
+                            true,
+                        );
+                    }
+                    if let Some(ref guild) = profile.guild {
+                        e = e.field("Guild", format!("<{}> @ {}", guild.name, guild.realm), true);
+                    }
+                    if let Some(ref thumbnail) = profile.thumbnail {
+                        e = e.thumbnail(battlenet::thumbnail_url(&region, thumbnail));
+                    }
+
+                    e.field("Armory", &armory, false)
+                })
+            });
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
+
+/// Serenity command for breaking down a guild's roster from Battle.net.
+pub struct WowCensus {
+    opts: Arc<CommandOptions>,
+    client: BattleNetClient,
+    region: String,
+}
+
+impl WowCensus {
+    pub fn new(client_data: &Arc<Mutex<ShareMap>>) -> Option<WowCensus> {
+        let (client, region) = client_from_conf(client_data)?;
+
+        let mut opts = CommandOptions::default();
+        opts.desc = Some(format!(
+            "Break down a World of Warcraft guild's roster by class, race, level and rank. The region defaults to `{}`.",
+            region
+        ));
+        opts.usage = Some("GUILD @ REALM [REGION]".into());
+        opts.example = Some("Drakon @ Argent Dawn eu".into());
+        opts.min_args = Some(3);
+
+        Some(WowCensus {
+            opts: Arc::new(opts),
+            client,
+            region,
+        })
+    }
+}
+
+impl Command for WowCensus {
+    fn execute(&self, _ctx: &mut Context, msg: &Message, args: Args) -> Result<(), CommandError> {
+        let (guild, realm, region) = match split_guild_target(&args, &self.region) {
+            Some(target) => target,
+            None => {
+                usage_error_embed(
+                    "wow census",
+                    "You must specify a guild name and realm, separated by `@`.",
+                    Arc::clone(&self.opts),
+                    msg,
+                );
+                return Ok(());
+            }
+        };
+
+        // Gather everything the closure will need here.
+        let usr_mention = msg.author.mention();
+        let channel_id = msg.channel_id;
+        let client = self.client.clone();
+
+        run_on_worker(move || {
+            let roster = match client.guild_roster(&region, &realm, &guild) {
+                Ok(roster) => roster,
+                Err(err) => {
+                    handle_bnet_err(err, channel_id, &usr_mention, "Guild not found.");
+                    return;
+                }
+            };
+
+            let census = roster.census();
+            if census.total == 0 {
+                error_embed(&channel_id, "That guild has no members.", Some(&usr_mention), |e| e);
+                return;
+            }
+
+            let classes = render_breakdown(&census.classes, census.total, |it| it.to_string());
+            let races = render_breakdown(&census.races, census.total, |it| it.to_string());
+            let levels = render_breakdown(&census.levels, census.total, |it| format!("{}-{}", it, it + 9));
+            let ranks = render_breakdown(&census.ranks, census.total, |it| match *it {
+                0 => "Guild Master".to_string(),
+                rank => format!("Rank {}", rank),
+            });
+
+            let _ = channel_id.send_message(|m| {
+                m.content(usr_mention).embed(|e| {
+                    e.title(format!("Census for <{}> @ {} ({})", roster.name, roster.realm, region.to_uppercase()))
+                        .colour(*COLOUR_BATTLENET)
+                        .description(format!("{} members", census.total))
+                        .field("Classes", classes, true)
+                        .field("Races", races, true)
+                        .field("Levels", levels, true)
+                        .field("Ranks", ranks, true)
+                })
+            });
+        });
+
+        Ok(())
+    }
+
+    fn options(&self) -> Arc<CommandOptions> {
+        Arc::clone(&self.opts)
+    }
+}
@@ -0,0 +1,535 @@
+//! Minimal client for the Condenser URL shortener API.
+use std::fmt;
+use std::io;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use chrono::offset::FixedOffset;
+use chrono::DateTime;
+use hyper;
+use parking_lot::Mutex;
+use reqwest::header::{Headers, UserAgent};
+use reqwest::{self, Client, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use url::Url;
+
+use constants::USER_AGENT;
+
+header!{ (XApiKey, "X-API-Key") => [String] }
+
+/// Errors returned by the Condenser client.
+#[derive(Debug)]
+pub enum CondenserError {
+    /// The requested code doesn't exist.
+    NotFound,
+    /// The API key was rejected, or is required but wasn't configured.
+    Unauthorized,
+    /// The requested code already exists.
+    Conflict,
+    /// Any other unexpected HTTP status.
+    Status(StatusCode),
+    /// The request couldn't be built from the provided code.
+    InvalidRequest,
+    /// The request couldn't be sent, or no response was received.
+    Transport(reqwest::Error),
+    /// The response couldn't be parsed.
+    Parse(reqwest::Error),
+    /// Too many recent requests have failed, so the server wasn't contacted.
+    Unavailable,
+}
+
+impl CondenserError {
+    /// Whether the error is likely to go away by itself, i.e. the server is down or overloaded.
+    pub fn is_transient(&self) -> bool {
+        match *self {
+            CondenserError::Transport(_) => true,
+            CondenserError::Status(code) => code.is_server_error(),
+            _ => false,
+        }
+    }
+
+    /// Whether the request failed while connecting, so it can't have reached the server.
+    fn is_connect_failure(&self) -> bool {
+        let err = match *self {
+            CondenserError::Transport(ref err) => err,
+            _ => return false,
+        };
+
+        let is_connect_kind = |err: &io::Error| match err.kind() {
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::AddrNotAvailable => true,
+            _ => false,
+        };
+        match err.get_ref() {
+            Some(inner) => match inner.downcast_ref::<hyper::Error>() {
+                Some(&hyper::Error::Io(ref io)) => is_connect_kind(io),
+                _ => inner.downcast_ref::<io::Error>().map_or(false, is_connect_kind),
+            },
+            None => false,
+        }
+    }
+}
+
+impl fmt::Display for CondenserError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CondenserError::NotFound => write!(f, "not found"),
+            CondenserError::Unauthorized => write!(f, "API key rejected"),
+            CondenserError::Conflict => write!(f, "code already exists"),
+            CondenserError::Status(code) => write!(f, "unexpected status {}", code),
+            CondenserError::InvalidRequest => write!(f, "invalid request"),
+            CondenserError::Transport(ref err) => write!(f, "transport error: {}", err),
+            CondenserError::Parse(ref err) => write!(f, "parse error: {}", err),
+            CondenserError::Unavailable => write!(f, "server unavailable"),
+        }
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct ShortenRequest<'a> {
+    url: &'a str,
+    code: Option<&'a str>,
+    meta: Option<&'a str>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ShortenResponse {
+    #[serde(with = "url_serde")]
+    short_url: Url,
+}
+
+#[derive(Serialize, Debug)]
+struct EditRequest<'a> {
+    code: &'a str,
+    url: &'a str,
+    meta: Option<&'a str>,
+}
+
+#[derive(Serialize, Debug)]
+struct DeleteRequest<'a> {
+    code: &'a str,
+}
+
+/// Response to requests which modify an existing code.
+#[derive(Deserialize, Debug)]
+struct StatusResponse {
+    status: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ListResponse {
+    codes: Vec<CodeEntry>,
+}
+
+/// Bars used by `LinkStats::sparkline`, from lowest to highest.
+const SPARK_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// A code's destination and metadata.
+#[derive(Deserialize, Debug)]
+pub struct CodeMeta {
+    #[serde(with = "url_serde")]
+    pub full_url: Url,
+    pub meta: LinkMetadata,
+    /// Click statistics, if the server tracks them.
+    #[serde(default)]
+    pub stats: Option<LinkStats>,
+}
+
+/// Click statistics for a code.
+#[derive(Deserialize, Default, Debug)]
+pub struct LinkStats {
+    /// Number of times the short URL has been followed.
+    #[serde(default)]
+    pub hits: u64,
+    #[serde(default)]
+    pub last_accessed: Option<DateTime<FixedOffset>>,
+    /// Hits per day, oldest first and ending with today.
+    #[serde(default)]
+    pub daily_hits: Vec<u64>,
+}
+
+impl LinkStats {
+    /// Renders the last `days` of daily hits as a sparkline, e.g. `▁▁▃█▂`. Returns None if there are no daily hits.
+    pub fn sparkline(&self, days: usize) -> Option<String> {
+        let start = self.daily_hits.len().saturating_sub(days);
+        let hits = &self.daily_hits[start..];
+        let max = *hits.iter().max()?;
+
+        Some(
+            hits.iter()
+                .map(|&it| {
+                    let idx = if max == 0 { 0 } else { it * (SPARK_BARS.len() as u64 - 1) / max };
+                    SPARK_BARS[idx as usize]
+                })
+                .collect(),
+        )
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct LinkMetadata {
+    /// Name of the API key which created the code.
+    pub owner: String,
+    pub time: DateTime<FixedOffset>,
+    /// Free-form metadata attached when the code was created.
+    pub user_meta: Option<String>,
+}
+
+/// A code, as returned when listing codes.
+#[derive(Deserialize, Debug)]
+pub struct CodeEntry {
+    pub code: String,
+    #[serde(with = "url_serde")]
+    pub full_url: Url,
+    pub meta: LinkMetadata,
+}
+
+/// How requests which fail with transient errors are retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first.
+    pub attempts: u32,
+    /// Delay before the first retry. Each further retry waits twice as long as the last.
+    pub initial_delay: Duration,
+    /// Upper bound on the delay between retries.
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// A policy which never retries.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 1,
+            initial_delay: Duration::from_secs(0),
+            max_delay: Duration::from_secs(0),
+        }
+    }
+
+    /// Delay before the given retry, starting at 1.
+    fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.pow((retry - 1).min(16));
+        (self.initial_delay * factor).min(self.max_delay)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            attempts: 3,
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+type TripHandler = Fn(&CondenserError) + Send + Sync;
+
+#[derive(Default)]
+struct BreakerState {
+    failures: u32,
+    open_until: Option<Instant>,
+    /// When the request probing a half-open breaker was let through.
+    probe_started: Option<Instant>,
+}
+
+/// Stops requests to a server after repeated transient failures, so users get a fast answer rather than waiting on
+/// retries which are bound to fail. Once the cooldown has passed, a single request is let through to probe the server;
+/// the breaker closes if it succeeds, and reopens if not. If the probe never reports back, another is let through after
+/// a further cooldown.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<BreakerState>,
+    on_trip: Option<Box<TripHandler>>,
+}
+
+impl CircuitBreaker {
+    /// Creates a breaker which opens after `threshold` consecutive failed requests, for `cooldown`.
+    pub fn new(threshold: u32, cooldown: Duration) -> CircuitBreaker {
+        CircuitBreaker {
+            threshold,
+            cooldown,
+            state: Mutex::new(BreakerState::default()),
+            on_trip: None,
+        }
+    }
+
+    /// Sets a handler called with the last error when the breaker opens. It isn't called again when a probe fails,
+    /// only once the server has recovered and failed again.
+    pub fn on_trip<F>(mut self, handler: F) -> CircuitBreaker
+    where
+        F: Fn(&CondenserError) + Send + Sync + 'static,
+    {
+        self.on_trip = Some(Box::new(handler));
+        self
+    }
+
+    /// Whether requests are currently being refused.
+    pub fn is_open(&self) -> bool {
+        let state = self.state.lock();
+        match state.open_until {
+            Some(until) => Instant::now() < until || self.probing(&state),
+            None => false,
+        }
+    }
+
+    /// Checks whether a request may be sent. Once the cooldown has passed, this claims the probe.
+    fn allow(&self) -> bool {
+        let mut state = self.state.lock();
+        match state.open_until {
+            None => true,
+            Some(until) if Instant::now() < until || self.probing(&state) => false,
+            Some(_) => {
+                state.probe_started = Some(Instant::now());
+                true
+            }
+        }
+    }
+
+    fn probing(&self, state: &BreakerState) -> bool {
+        state.probe_started.map_or(false, |it| it.elapsed() < self.cooldown)
+    }
+
+    fn record_success(&self) {
+        let mut state = self.state.lock();
+        state.failures = 0;
+        state.open_until = None;
+        state.probe_started = None;
+    }
+
+    fn record_failure(&self, err: &CondenserError) {
+        let tripped = {
+            let mut state = self.state.lock();
+            state.probe_started = None;
+            state.failures += 1;
+            if state.failures < self.threshold {
+                return;
+            }
+
+            let was_open = state.open_until.is_some();
+            state.open_until = Some(Instant::now() + self.cooldown);
+            !was_open
+        };
+
+        if tripped {
+            warn!("Condenser circuit breaker tripped after {} failures: {}", self.threshold, err);
+            if let Some(ref handler) = self.on_trip {
+                handler(err);
+            }
+        }
+    }
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> CircuitBreaker {
+        CircuitBreaker::new(5, Duration::from_secs(60))
+    }
+}
+
+/// A Condenser server as configured, either globally or for a single guild.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ServerConf<'a> {
+    pub server: Option<&'a str>,
+    pub key: Option<&'a str>,
+}
+
+/// Picks the server and API key to use, preferring a guild's own server to the global one. A guild's key is only ever
+/// sent to the guild's server, so there's no falling back to the global key. If `need_key` is set, returns None unless
+/// the chosen server has a key.
+pub fn resolve_server(guild: ServerConf, global: ServerConf, need_key: bool) -> Option<(Url, Option<String>)> {
+    let conf = if guild.server.is_some() { guild } else { global };
+    let server = Url::parse(conf.server?).ok()?;
+    if need_key && conf.key.is_none() {
+        return None;
+    }
+    Some((server, conf.key.map(String::from)))
+}
+
+/// Client for a Condenser server. Cheap to clone; clones share a circuit breaker.
+#[derive(Clone)]
+pub struct CondenserClient {
+    client: Client,
+    server: Url,
+    key: Option<String>,
+    retry: RetryPolicy,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl CondenserClient {
+    /// Creates a client for the server at `server`. Without an API key, only `meta` is available.
+    pub fn new(server: Url, key: Option<String>) -> CondenserClient {
+        let mut headers = Headers::new();
+        headers.set(UserAgent::new(USER_AGENT));
+        let client = Client::builder()
+            .default_headers(headers)
+            .timeout(Some(Duration::from_secs(10)))
+            .build()
+            .expect("Reqwest client init");
+
+        CondenserClient {
+            client,
+            server,
+            key,
+            retry: RetryPolicy::default(),
+            breaker: Arc::new(CircuitBreaker::default()),
+        }
+    }
+
+    /// Replaces the retry policy.
+    pub fn with_retry(mut self, retry: RetryPolicy) -> CondenserClient {
+        self.retry = retry;
+        self
+    }
+
+    /// Replaces the circuit breaker, e.g. to share one between clients for the same server.
+    pub fn with_breaker(mut self, breaker: Arc<CircuitBreaker>) -> CondenserClient {
+        self.breaker = breaker;
+        self
+    }
+
+    /// The server's base URL.
+    pub fn server(&self) -> &Url {
+        &self.server
+    }
+
+    /// Whether an API key is configured.
+    pub fn has_key(&self) -> bool {
+        self.key.is_some()
+    }
+
+    /// Builds the public short URL for a code.
+    pub fn short_url(&self, code: &str) -> Result<Url, CondenserError> {
+        self.endpoint(&[code])
+    }
+
+    /// Shortens a URL, optionally with a specific code, returning the short URL.
+    pub fn shorten(&self, url: &str, code: Option<&str>, meta: Option<&str>) -> Result<Url, CondenserError> {
+        let request = ShortenRequest { url, code, meta };
+        let endpoint = self.endpoint(&["api", "shorten"])?;
+
+        self.send::<ShortenResponse, _>(false, || {
+            let mut builder = self.client.post(endpoint.clone());
+            builder.json(&request);
+            self.authed(builder)
+        }).map(|it| it.short_url)
+    }
+
+    /// Fetches a code's destination and metadata.
+    pub fn meta(&self, code: &str) -> Result<CodeMeta, CondenserError> {
+        let endpoint = self.endpoint(&["api", "meta", code])?;
+        self.send(true, || Ok(self.client.get(endpoint.clone())))
+    }
+
+    /// Changes an existing code's destination and user metadata.
+    pub fn edit(&self, code: &str, url: &str, meta: Option<&str>) -> Result<(), CondenserError> {
+        let request = EditRequest { code, url, meta };
+        let endpoint = self.endpoint(&["api", "edit"])?;
+
+        let response = self.send::<StatusResponse, _>(false, || {
+            let mut builder = self.client.post(endpoint.clone());
+            builder.json(&request);
+            self.authed(builder)
+        })?;
+        if response.status == "noexist" {
+            return Err(CondenserError::NotFound);
+        }
+        Ok(())
+    }
+
+    /// Deletes a code.
+    pub fn delete(&self, code: &str) -> Result<(), CondenserError> {
+        let request = DeleteRequest { code };
+        let endpoint = self.endpoint(&["api", "delete"])?;
+
+        let response = self.send::<StatusResponse, _>(false, || {
+            let mut builder = self.client.post(endpoint.clone());
+            builder.json(&request);
+            self.authed(builder)
+        })?;
+        if response.status == "noexist" {
+            return Err(CondenserError::NotFound);
+        }
+        Ok(())
+    }
+
+    /// Lists every code on the server.
+    pub fn list(&self) -> Result<Vec<CodeEntry>, CondenserError> {
+        let endpoint = self.endpoint(&["api", "list"])?;
+
+        self.send::<ListResponse, _>(true, || self.authed(self.client.get(endpoint.clone())))
+            .map(|it| it.codes)
+    }
+
+    fn endpoint(&self, segments: &[&str]) -> Result<Url, CondenserError> {
+        let mut url = self.server.clone();
+        url.set_path("");
+        {
+            let mut path = url.path_segments_mut().map_err(|_| CondenserError::InvalidRequest)?;
+            path.clear().extend(segments);
+        }
+        Ok(url)
+    }
+
+    fn authed(&self, mut builder: RequestBuilder) -> Result<RequestBuilder, CondenserError> {
+        let key = self.key.clone().ok_or(CondenserError::Unauthorized)?;
+        builder.header(XApiKey(key));
+        Ok(builder)
+    }
+
+    /// Sends the request made by `build`, retrying it on transient errors, and tracks the outcome in the circuit
+    /// breaker. Request builders can't be reused, hence building a fresh one for each attempt. Requests which aren't
+    /// `idempotent` are only retried if they failed while connecting, as otherwise the first attempt may have been
+    /// applied.
+    fn send<T, F>(&self, idempotent: bool, build: F) -> Result<T, CondenserError>
+    where
+        T: DeserializeOwned,
+        F: Fn() -> Result<RequestBuilder, CondenserError>,
+    {
+        // Building only fails for reasons which don't change between attempts, so it's checked up front.
+        let mut builder = build()?;
+        if !self.breaker.allow() {
+            return Err(CondenserError::Unavailable);
+        }
+
+        let mut attempt = 1;
+        loop {
+            match self.send_once::<T>(builder) {
+                Err(ref err)
+                    if err.is_transient()
+                        && (idempotent || err.is_connect_failure())
+                        && attempt < self.retry.attempts =>
+                {
+                    debug!("Condenser request failed ({}); retrying", err);
+                }
+                Err(err) => {
+                    if err.is_transient() {
+                        self.breaker.record_failure(&err);
+                    } else {
+                        self.breaker.record_success();
+                    }
+                    return Err(err);
+                }
+                Ok(it) => {
+                    self.breaker.record_success();
+                    return Ok(it);
+                }
+            }
+
+            thread::sleep(self.retry.delay(attempt));
+            attempt += 1;
+            builder = build()?;
+        }
+    }
+
+    fn send_once<T: DeserializeOwned>(&self, mut builder: RequestBuilder) -> Result<T, CondenserError> {
+        let mut response = builder.send().map_err(CondenserError::Transport)?;
+
+        match response.status() {
+            StatusCode::Ok => response.json::<T>().map_err(CondenserError::Parse),
+            StatusCode::NotFound => Err(CondenserError::NotFound),
+            StatusCode::Unauthorized | StatusCode::Forbidden => Err(CondenserError::Unauthorized),
+            StatusCode::Conflict => Err(CondenserError::Conflict),
+            code => Err(CondenserError::Status(code)),
+        }
+    }
+}
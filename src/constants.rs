@@ -7,7 +7,13 @@ pub const CONF_DISCORD_APPID: &str = "discord.appid";
 pub const CONF_DISCORD_TOKEN: &str = "discord.token";
 pub const CONF_DISCORD_OWNERS: &str = "discord.owners";
 
+pub const CONF_STORAGE_PATH: &str = "storage.path";
+
+pub const CONF_SNARK_FILE: &str = "snark.file";
+
 pub const CONF_BNET_TOKEN: &str = "battlenet.token";
+pub const CONF_BNET_API: &str = "battlenet.api";
+pub const CONF_BNET_REGION: &str = "battlenet.region";
 
 pub const CONF_CONDENSER_SRV: &str = "condenser.server";
 pub const CONF_CONDENSER_KEY: &str = "condenser.key";
@@ -15,6 +21,11 @@ pub const CONF_CONDENSER_KEY: &str = "condenser.key";
 // Metadata
 pub const USER_AGENT: &str = concat!("drakonid-rs/", env!("CARGO_PKG_VERSION"));
 
+// Discord limits, in characters
+pub const EMBED_FIELD_LIMIT: usize = 1024;
+pub const EMBED_DESCRIPTION_LIMIT: usize = 2048;
+pub const EMBED_TOTAL_LIMIT: usize = 6000;
+
 // Colours
 lazy_static! {
     // Global colours
@@ -23,4 +34,8 @@ lazy_static! {
 
     // Subsystem specific colours
     pub static ref COLOUR_CONDENSER: Colour = Colour::blue();
+    pub static ref COLOUR_STREAM: Colour = Colour::purple();
+    pub static ref COLOUR_BATTLENET: Colour = Colour::dark_gold();
+    pub static ref COLOUR_QUOTES: Colour = Colour::teal();
+    pub static ref COLOUR_GAMES: Colour = Colour::dark_green();
 }
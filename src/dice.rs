@@ -0,0 +1,393 @@
+//! Dice notation parser and roller, e.g. `4d6kh3`, `2d20+5`, `3d6!` or `adv`.
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+
+/// Maximum number of dice in a single expression, not counting explosions.
+pub const MAX_DICE: u32 = 100;
+
+/// Maximum number of sides on a single die.
+pub const MAX_SIDES: u32 = 1_000_000;
+
+/// Maximum magnitude of a constant modifier.
+pub const MAX_CONSTANT: i64 = 1_000_000;
+
+/// Maximum number of extra dice a single group may add by exploding.
+pub const MAX_EXPLOSIONS: usize = 100;
+
+/// Errors from parsing a dice expression.
+#[derive(Debug, PartialEq)]
+pub enum DiceError {
+    /// The expression was empty.
+    Empty,
+    /// Something unexpected was found at the given position.
+    Unexpected(usize),
+    /// More than `MAX_DICE` dice were requested.
+    TooManyDice,
+    /// A die had no sides, or more than `MAX_SIDES`.
+    InvalidSides,
+    /// A constant was larger than `MAX_CONSTANT`.
+    ConstantTooLarge,
+    /// A keep/drop modifier asked for no dice, or more dice than were rolled.
+    InvalidKeep,
+    /// Exploding a one-sided die would never terminate.
+    InfiniteExplosion,
+}
+
+impl fmt::Display for DiceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DiceError::Empty => write!(f, "no dice to roll"),
+            DiceError::Unexpected(pos) => write!(f, "unexpected input at position {}", pos + 1),
+            DiceError::TooManyDice => write!(f, "at most {} dice can be rolled at once", MAX_DICE),
+            DiceError::InvalidSides => write!(f, "dice must have between 1 and {} sides", MAX_SIDES),
+            DiceError::ConstantTooLarge => write!(f, "modifiers must be at most {}", MAX_CONSTANT),
+            DiceError::InvalidKeep => write!(f, "can't keep or drop that many dice"),
+            DiceError::InfiniteExplosion => write!(f, "one-sided dice can't explode"),
+        }
+    }
+}
+
+/// Which dice of a group count towards the total.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keep {
+    All,
+    Highest(u32),
+    Lowest(u32),
+    /// Every die but the lowest N, counting any added by explosions.
+    DropLowest(u32),
+    /// Every die but the highest N, counting any added by explosions.
+    DropHighest(u32),
+}
+
+/// A group of identical dice, e.g. `4d6kh3`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiceGroup {
+    pub count: u32,
+    pub sides: u32,
+    pub explode: bool,
+    pub keep: Keep,
+}
+
+impl fmt::Display for DiceGroup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)?;
+        if self.explode {
+            write!(f, "!")?;
+        }
+        match self.keep {
+            Keep::All => Ok(()),
+            Keep::Highest(n) => write!(f, "kh{}", n),
+            Keep::Lowest(n) => write!(f, "kl{}", n),
+            Keep::DropLowest(n) => write!(f, "dl{}", n),
+            Keep::DropHighest(n) => write!(f, "dh{}", n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Term {
+    Dice(DiceGroup),
+    Constant(i64),
+}
+
+/// A parsed dice expression: a sum of dice groups and constants.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expression {
+    /// Each term, paired with whether it is subtracted.
+    pub terms: Vec<(bool, Term)>,
+}
+
+/// A single rolled die.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Die {
+    pub value: u32,
+    /// Whether the die counts towards the total.
+    pub kept: bool,
+    /// Whether the die was added by another die exploding.
+    pub exploded: bool,
+}
+
+/// The outcome of rolling one term.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TermResult {
+    Dice(DiceGroup, Vec<Die>),
+    Constant(i64),
+}
+
+impl TermResult {
+    /// The value this term contributes, before its sign is applied.
+    pub fn value(&self) -> i64 {
+        match *self {
+            TermResult::Dice(_, ref dice) => dice.iter().filter(|it| it.kept).map(|it| i64::from(it.value)).sum(),
+            TermResult::Constant(value) => value,
+        }
+    }
+}
+
+/// The outcome of rolling an expression.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RollResult {
+    pub total: i64,
+    /// Each term's result, paired with whether it was subtracted.
+    pub terms: Vec<(bool, TermResult)>,
+}
+
+impl Expression {
+    /// Rolls every dice group in the expression and sums the result.
+    pub fn roll<R: Rng>(&self, rng: &mut R) -> RollResult {
+        let terms = self.terms
+            .iter()
+            .map(|&(negative, ref term)| {
+                let result = match *term {
+                    Term::Dice(ref group) => TermResult::Dice(group.clone(), roll_group(group, rng)),
+                    Term::Constant(value) => TermResult::Constant(value),
+                };
+                (negative, result)
+            })
+            .collect::<Vec<_>>();
+
+        let total = terms
+            .iter()
+            .map(|&(negative, ref result)| if negative { -result.value() } else { result.value() })
+            .sum();
+
+        RollResult { total, terms }
+    }
+}
+
+fn roll_group<R: Rng>(group: &DiceGroup, rng: &mut R) -> Vec<Die> {
+    let mut dice = Vec::with_capacity(group.count as usize);
+    for _ in 0..group.count {
+        dice.push(Die {
+            value: rng.gen_range(1, group.sides + 1),
+            kept: true,
+            exploded: false,
+        });
+    }
+
+    if group.explode {
+        // Each die showing its maximum adds another die, which may itself explode.
+        let mut idx = 0;
+        while idx < dice.len() && dice.len() - (group.count as usize) < MAX_EXPLOSIONS {
+            if dice[idx].value == group.sides {
+                dice.push(Die {
+                    value: rng.gen_range(1, group.sides + 1),
+                    kept: true,
+                    exploded: true,
+                });
+            }
+            idx += 1;
+        }
+    }
+
+    // Dropping the lowest N is keeping the highest of the rest, and vice versa. The parser ensures fewer than
+    // `group.count` dice are dropped.
+    let (keep, highest) = match group.keep {
+        Keep::All => return dice,
+        Keep::Highest(n) => (n as usize, true),
+        Keep::Lowest(n) => (n as usize, false),
+        Keep::DropLowest(n) => (dice.len() - n as usize, true),
+        Keep::DropHighest(n) => (dice.len() - n as usize, false),
+    };
+
+    let mut order = (0..dice.len()).collect::<Vec<_>>();
+    order.sort_by_key(|it| dice[*it].value);
+    if highest {
+        order.reverse();
+    }
+    for idx in order.into_iter().skip(keep) {
+        dice[idx].kept = false;
+    }
+
+    dice
+}
+
+impl FromStr for Expression {
+    type Err = DiceError;
+
+    fn from_str(input: &str) -> Result<Expression, DiceError> {
+        let input = input
+            .chars()
+            .filter(|it| !it.is_whitespace())
+            .collect::<String>()
+            .to_lowercase();
+        if input.is_empty() {
+            return Err(DiceError::Empty);
+        }
+
+        let mut parser = Parser {
+            input: input.as_bytes(),
+            pos: 0,
+        };
+        let mut terms = Vec::new();
+        let mut negative = false;
+        loop {
+            terms.push((negative, parser.term()?));
+            negative = match parser.next() {
+                Some(b'+') => false,
+                Some(b'-') => true,
+                None => break,
+                Some(_) => return Err(DiceError::Unexpected(parser.pos - 1)),
+            };
+        }
+
+        let dice = terms
+            .iter()
+            .map(|it| match it.1 {
+                Term::Dice(ref group) => group.count,
+                Term::Constant(_) => 0,
+            })
+            .sum::<u32>();
+        if dice > MAX_DICE {
+            return Err(DiceError::TooManyDice);
+        }
+
+        Ok(Expression { terms })
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let next = self.peek();
+        if next.is_some() {
+            self.pos += 1;
+        }
+        next
+    }
+
+    fn eat(&mut self, keyword: &str) -> bool {
+        if self.input[self.pos..].starts_with(keyword.as_bytes()) {
+            self.pos += keyword.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Reads a run of digits, returning `None` if there are none. Values too large for a `u64` saturate, and are
+    /// rejected by the callers' range checks.
+    fn number(&mut self) -> Option<u64> {
+        let start = self.pos;
+        let mut value = 0u64;
+        while let Some(digit @ b'0'...b'9') = self.peek() {
+            value = value.saturating_mul(10).saturating_add(u64::from(digit - b'0'));
+            self.pos += 1;
+        }
+        if self.pos == start {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    fn term(&mut self) -> Result<Term, DiceError> {
+        if self.eat("adv") {
+            return Ok(Term::Dice(DiceGroup {
+                count: 2,
+                sides: 20,
+                explode: false,
+                keep: Keep::Highest(1),
+            }));
+        }
+        if self.eat("dis") {
+            return Ok(Term::Dice(DiceGroup {
+                count: 2,
+                sides: 20,
+                explode: false,
+                keep: Keep::Lowest(1),
+            }));
+        }
+
+        let start = self.pos;
+        let count = self.number();
+        if self.peek() != Some(b'd') {
+            return match count {
+                Some(value) if value > MAX_CONSTANT as u64 => Err(DiceError::ConstantTooLarge),
+                Some(value) => Ok(Term::Constant(value as i64)),
+                None => Err(DiceError::Unexpected(start)),
+            };
+        }
+        self.pos += 1;
+
+        let count = count.unwrap_or(1);
+        if count == 0 || count > u64::from(MAX_DICE) {
+            return Err(DiceError::TooManyDice);
+        }
+        let count = count as u32;
+
+        let sides = if self.peek() == Some(b'%') {
+            self.pos += 1;
+            100
+        } else {
+            match self.number() {
+                Some(sides) if sides >= 1 && sides <= u64::from(MAX_SIDES) => sides as u32,
+                Some(_) => return Err(DiceError::InvalidSides),
+                None => return Err(DiceError::Unexpected(self.pos)),
+            }
+        };
+
+        let explode = self.peek() == Some(b'!');
+        if explode {
+            if sides == 1 {
+                return Err(DiceError::InfiniteExplosion);
+            }
+            self.pos += 1;
+        }
+
+        let keep = self.keep(count)?;
+
+        Ok(Term::Dice(DiceGroup {
+            count,
+            sides,
+            explode,
+            keep,
+        }))
+    }
+
+    /// Parses an optional `kh N`, `kl N`, `dh N` or `dl N` suffix. `k N` is short for `kh N`, and `N` defaults to 1.
+    fn keep(&mut self, count: u32) -> Result<Keep, DiceError> {
+        let (keep, highest) = match self.peek() {
+            Some(b'k') => (true, true),
+            Some(b'd') => (false, false),
+            _ => return Ok(Keep::All),
+        };
+        self.pos += 1;
+
+        let highest = match self.peek() {
+            Some(b'h') => {
+                self.pos += 1;
+                true
+            }
+            Some(b'l') => {
+                self.pos += 1;
+                false
+            }
+            // Plain `k` keeps the highest, plain `d` drops the lowest.
+            _ => highest,
+        };
+
+        let n = self.number().unwrap_or(1);
+        if n == 0 || n > u64::from(count) || (!keep && n == u64::from(count)) {
+            return Err(DiceError::InvalidKeep);
+        }
+        let n = n as u32;
+
+        Ok(match (keep, highest) {
+            (true, true) => Keep::Highest(n),
+            (true, false) => Keep::Lowest(n),
+            (false, true) => Keep::DropHighest(n),
+            (false, false) => Keep::DropLowest(n),
+        })
+    }
+}
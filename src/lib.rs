@@ -1,7 +1,7 @@
 #![cfg_attr(feature = "clippy", feature(plugin))]
 #![cfg_attr(feature = "clippy", plugin(clippy))]
 #![feature(extern_prelude)]
-#![recursion_limit = "128"] // Necessary for SVar generation via mashup
+#![recursion_limit = "256"] // Necessary for SVar generation via mashup
 #![allow(unknown_lints)]
 #![warn(clippy)]
 
@@ -10,6 +10,7 @@ extern crate config;
 extern crate fuzzy_match;
 #[macro_use]
 extern crate hyper;
+extern crate image;
 #[macro_use]
 extern crate lazy_static;
 #[macro_use]
@@ -17,6 +18,7 @@ extern crate log;
 #[macro_use]
 extern crate mashup;
 extern crate parking_lot;
+extern crate qrcode;
 extern crate rand;
 extern crate regex;
 extern crate reqwest;
@@ -33,32 +35,65 @@ extern crate url_serde;
 use std::sync::Arc;
 use std::{thread, time};
 
+use serenity::model::channel::Message;
+use serenity::model::event::PresenceUpdateEvent;
 use serenity::model::gateway::Ready;
+use serenity::model::guild::Member;
+use serenity::model::id::{GuildId, UserId};
 use serenity::prelude::*;
 use serenity::Client;
 
+use types::GuildConfigMarker;
+
 #[macro_use]
 pub mod utils;
 
+pub mod battlenet;
+pub mod bulk;
 pub mod commands;
+pub mod condenser;
 pub mod constants;
+pub mod dice;
+pub mod link_policy;
 pub mod server;
+pub mod snark;
 pub mod types;
 pub mod workers;
 
 const RESTART_SECONDS: u64 = 30;
 
-// TODO: Replace this with the live event handler
 struct Handler;
 impl EventHandler for Handler {
     fn ready(&self, ctx: Context, _: Ready) {
         ctx.reset_presence();
     }
+
+    fn guild_member_addition(&self, ctx: Context, guild_id: GuildId, member: Member) {
+        let store = guild_conf!(ctx.data);
+        commands::announcements::member_joined(&store, guild_id, &member);
+    }
+
+    fn presence_update(&self, ctx: Context, event: PresenceUpdateEvent) {
+        let store = guild_conf!(ctx.data);
+        commands::announcements::presence_updated(&store, &event);
+    }
+
+    fn message(&self, ctx: Context, msg: Message) {
+        commands::snark::on_message(&ctx.data, &msg);
+    }
 }
 
 pub fn run(conf_loc: &str, is_wrapped: bool) {
     let mut conf = config::Config::default();
     conf.set_default(constants::CONF_IS_WRAPPED, false)
+        .unwrap()
+        .set_default(constants::CONF_STORAGE_PATH, "./data")
+        .unwrap()
+        .set_default(constants::CONF_SNARK_FILE, "./snark.json")
+        .unwrap()
+        .set_default(constants::CONF_BNET_API, "https://{region}.api.battle.net/")
+        .unwrap()
+        .set_default(constants::CONF_BNET_REGION, "us")
         .unwrap()
         .merge(
             config::File::with_name(
@@ -77,13 +112,42 @@ pub fn run(conf_loc: &str, is_wrapped: bool) {
 
     let token = conf.get_str(constants::CONF_DISCORD_TOKEN)
         .expect("No token specified in configuration.");
+    let storage_path = conf.get_str(constants::CONF_STORAGE_PATH)
+        .expect("No storage path specified in configuration.");
+    let guild_conf = Arc::new(
+        server::config::ConfigStore::new(storage_path).expect("Unable to open guild configuration storage."),
+    );
+
+    let snark_file = conf.get_str(constants::CONF_SNARK_FILE)
+        .expect("No snark file specified in configuration.");
+    let snark = match snark::SnarkEngine::load(&snark_file) {
+        Ok(engine) => engine,
+        Err(e) => {
+            warn!("Unable to load snark responses from {}, snark is disabled: {}", snark_file, e);
+            snark::SnarkEngine::empty()
+        }
+    };
+
+    let owners = conf.get::<Vec<u64>>(constants::CONF_DISCORD_OWNERS)
+        .unwrap_or_else(|_| Vec::new())
+        .into_iter()
+        .map(UserId)
+        .collect();
+    let perms = server::permissions::Permissions::new(owners, Arc::clone(&guild_conf));
+
     let mut client = Client::new(&token, Handler).expect("Serenity client init failed.");
 
+    // Needs the HTTP token set by Client::new to lift mutes and bans.
+    commands::discipline::start_expiry_thread(Arc::clone(&guild_conf));
+
     // Attach config to Serenity's shared data (which is exposed in Context structs later)
     debug!("Attaching configuration to Client/Context data.");
     {
         let mut lock = client.data.lock();
         lock.insert::<types::ConfigMarker>(Arc::new(conf));
+        lock.insert::<types::GuildConfigMarker>(guild_conf);
+        lock.insert::<types::PermissionsMarker>(Arc::new(perms));
+        lock.insert::<types::SnarkMarker>(Arc::new(snark));
     }
 
     // Attach Standard Framework
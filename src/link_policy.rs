@@ -0,0 +1,240 @@
+//! Rules for which URLs may be shortened, so the shortener can't be used to disguise unwanted links.
+use std::fmt;
+use std::net::{IpAddr, ToSocketAddrs};
+use std::time::Duration;
+
+use reqwest::header::{Headers, Location, UserAgent};
+use reqwest::{Client, RedirectPolicy};
+use url::{Host, Url};
+
+use constants::USER_AGENT;
+
+/// Hosts of well-known URL shorteners.
+pub const KNOWN_SHORTENERS: &[&str] = &[
+    "bit.ly", "bl.ink", "buff.ly", "cutt.ly", "goo.gl", "is.gd", "ow.ly", "rebrand.ly", "shorturl.at", "t.co",
+    "tiny.cc", "tinyurl.com", "v.gd",
+];
+
+/// Maximum number of redirects followed when checking a URL.
+const MAX_REDIRECTS: usize = 5;
+
+/// Why a URL was rejected.
+#[derive(Debug, PartialEq)]
+pub enum PolicyViolation {
+    /// The URL is longer than allowed.
+    TooLong { length: usize, max: usize },
+    /// The URL has no host.
+    NoHost,
+    /// The host is an IP address rather than a domain.
+    IpHost,
+    /// The host is only reachable on a local network, e.g. `localhost` or a name without a dot.
+    LocalHost,
+    /// The domain isn't on the allowlist.
+    NotAllowed(String),
+    /// The domain is on the blocklist.
+    Blocked(String),
+    /// The URL points at another URL shortener.
+    ShortLink(String),
+    /// The URL redirects somewhere which breaks the policy.
+    Redirect { to: Url, violation: Box<PolicyViolation> },
+}
+
+impl fmt::Display for PolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PolicyViolation::TooLong { length, max } => {
+                write!(f, "URL is {} characters long; the limit is {}.", length, max)
+            }
+            PolicyViolation::NoHost => write!(f, "URL has no host."),
+            PolicyViolation::IpHost => write!(f, "Links to IP addresses aren't allowed."),
+            PolicyViolation::LocalHost => write!(f, "Links to local hosts aren't allowed."),
+            PolicyViolation::NotAllowed(ref domain) => write!(f, "`{}` isn't on the list of allowed domains.", domain),
+            PolicyViolation::Blocked(ref domain) => write!(f, "`{}` is a blocked domain.", domain),
+            PolicyViolation::ShortLink(ref host) => {
+                write!(f, "`{}` is a URL shortener. Link the destination instead.", host)
+            }
+            // Where the URL redirects to isn't shown, as it may be an internal address.
+            PolicyViolation::Redirect { .. } => write!(f, "URL redirects to a link which isn't allowed here."),
+        }
+    }
+}
+
+/// A guild's rules for which URLs may be shortened.
+#[derive(Clone, Debug)]
+pub struct LinkPolicy {
+    /// If not empty, only these domains and their subdomains are allowed.
+    pub allowed: Vec<String>,
+    /// These domains and their subdomains are never allowed.
+    pub blocked: Vec<String>,
+    pub allow_ip_hosts: bool,
+    pub allow_short_links: bool,
+    /// Extra hosts treated as URL shorteners, such as the Condenser server itself.
+    pub shorteners: Vec<String>,
+    pub max_length: usize,
+}
+
+impl LinkPolicy {
+    /// Checks a URL against the policy, without making any requests.
+    pub fn check(&self, url: &Url) -> Result<(), PolicyViolation> {
+        let length = url.as_str().len();
+        if length > self.max_length {
+            return Err(PolicyViolation::TooLong {
+                length,
+                max: self.max_length,
+            });
+        }
+
+        self.check_host(url)
+    }
+
+    /// Checks a URL against the policy, then follows its redirects and checks every URL along the way. Redirects
+    /// which can't be followed are ignored, as the destination may simply not support `HEAD` requests. Hosts which
+    /// resolve to loopback or private addresses are never requested.
+    pub fn check_redirects(&self, url: &Url) -> Result<(), PolicyViolation> {
+        self.check(url)?;
+
+        let mut headers = Headers::new();
+        headers.set(UserAgent::new(USER_AGENT));
+        let client = match Client::builder()
+            .default_headers(headers)
+            .redirect(RedirectPolicy::none())
+            .timeout(Some(Duration::from_secs(5)))
+            .build()
+        {
+            Ok(client) => client,
+            Err(err) => {
+                warn!("Unable to build redirect checking client: {:?}", err);
+                return Ok(());
+            }
+        };
+
+        let mut current = url.clone();
+        for _ in 0..MAX_REDIRECTS {
+            let next = match next_hop(&client, &current) {
+                Some(next) => next,
+                None => return Ok(()),
+            };
+
+            if let Err(violation) = self.check_host(&next) {
+                info!("{} redirects to {}, which breaks the link policy: {}", url, next, violation);
+                return Err(PolicyViolation::Redirect {
+                    to: next,
+                    violation: Box::new(violation),
+                });
+            }
+            current = next;
+        }
+
+        Ok(())
+    }
+
+    fn check_host(&self, url: &Url) -> Result<(), PolicyViolation> {
+        let domain = match url.host() {
+            None => return Err(PolicyViolation::NoHost),
+            Some(Host::Ipv4(_)) | Some(Host::Ipv6(_)) => {
+                return if self.allow_ip_hosts {
+                    Ok(())
+                } else {
+                    Err(PolicyViolation::IpHost)
+                };
+            }
+            Some(Host::Domain(domain)) => domain.trim_right_matches('.').to_lowercase(),
+        };
+
+        if !domain.contains('.') || in_domain(&domain, "localhost") {
+            return Err(PolicyViolation::LocalHost);
+        }
+
+        if self.blocked.iter().any(|it| in_domain(&domain, it)) {
+            return Err(PolicyViolation::Blocked(domain));
+        }
+        if !self.allowed.is_empty() && !self.allowed.iter().any(|it| in_domain(&domain, it)) {
+            return Err(PolicyViolation::NotAllowed(domain));
+        }
+
+        let is_shortener = KNOWN_SHORTENERS.iter().any(|it| in_domain(&domain, it))
+            || self.shorteners.iter().any(|it| in_domain(&domain, it));
+        if is_shortener && !self.allow_short_links {
+            return Err(PolicyViolation::ShortLink(domain));
+        }
+
+        Ok(())
+    }
+}
+
+/// Normalises a domain entered by a user, e.g. `*.Example.com.` to `example.com`. Returns None if it isn't a domain.
+pub fn normalise_domain(input: &str) -> Option<String> {
+    let domain = input
+        .trim()
+        .trim_left_matches("*.")
+        .trim_right_matches('.')
+        .to_lowercase();
+
+    match Host::parse(&domain) {
+        Ok(Host::Domain(_)) if domain.contains('.') => Some(domain),
+        _ => None,
+    }
+}
+
+/// Checks whether `domain` is `parent` or one of its subdomains.
+fn in_domain(domain: &str, parent: &str) -> bool {
+    domain == parent || (domain.ends_with(parent) && domain[..domain.len() - parent.len()].ends_with('.'))
+}
+
+/// Checks whether an address is only reachable from the bot's own machine or network.
+fn is_internal(addr: &IpAddr) -> bool {
+    match *addr {
+        IpAddr::V4(v4) => {
+            let octets = v4.octets();
+            // "This network" (0.0.0.0/8) and carrier-grade NAT (100.64.0.0/10) addresses.
+            let reserved = octets[0] == 0 || (octets[0] == 100 && octets[1] & 0xc0 == 64);
+            v4.is_loopback() || v4.is_private() || v4.is_link_local() || v4.is_broadcast() || reserved
+        }
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            let mapped = v6.to_ipv4().map_or(false, |it| is_internal(&IpAddr::V4(it)));
+            // Unique local (fc00::/7) and link-local (fe80::/10) addresses.
+            v6.is_loopback() || v6.is_unspecified() || first & 0xfe00 == 0xfc00 || first & 0xffc0 == 0xfe80 || mapped
+        }
+    }
+}
+
+/// Resolves a URL's host, checking that none of its addresses are internal. The request which follows resolves the
+/// host again, so a host whose DNS answers change in between (DNS rebinding) can still be requested at an internal
+/// address. Only a `HEAD` request is made and its response is never shown, which limits what that can achieve.
+fn is_public(url: &Url) -> bool {
+    let addrs: Vec<IpAddr> = match url.host() {
+        Some(Host::Ipv4(addr)) => vec![IpAddr::V4(addr)],
+        Some(Host::Ipv6(addr)) => vec![IpAddr::V6(addr)],
+        Some(Host::Domain(domain)) => match (domain, url.port_or_known_default().unwrap_or(80)).to_socket_addrs() {
+            Ok(addrs) => addrs.map(|it| it.ip()).collect(),
+            Err(_) => return false,
+        },
+        None => return false,
+    };
+
+    !addrs.is_empty() && !addrs.iter().any(is_internal)
+}
+
+/// Requests a URL without following redirects, returning where it redirects to, if anywhere. Internal hosts aren't
+/// requested.
+fn next_hop(client: &Client, url: &Url) -> Option<Url> {
+    if !is_public(url) {
+        debug!("Not checking redirects for {}, as it isn't a public host", url);
+        return None;
+    }
+
+    let response = match client.head(url.clone()).send() {
+        Ok(response) => response,
+        Err(err) => {
+            debug!("Unable to check redirects for {}: {:?}", url, err);
+            return None;
+        }
+    };
+
+    if !response.status().is_redirection() {
+        return None;
+    }
+    let location = response.headers().get::<Location>()?;
+    url.join(location).ok()
+}
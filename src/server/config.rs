@@ -1 +1,260 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::PathBuf;
 
+use parking_lot::Mutex;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Value};
+use serenity::model::id::GuildId;
+
+use super::svar::{self, SVar};
+
+/// Errors which can occur when updating or persisting per-guild configuration.
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// A new SVar value was rejected by one of the SVar's constraints.
+    Invalid(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ConfigError::Io(ref err) => write!(f, "I/O error: {}", err),
+            ConfigError::Json(ref err) => write!(f, "JSON error: {}", err),
+            ConfigError::Invalid(ref reason) => write!(f, "invalid value: {}", reason),
+        }
+    }
+}
+
+impl From<io::Error> for ConfigError {
+    fn from(err: io::Error) -> Self {
+        ConfigError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ConfigError {
+    fn from(err: serde_json::Error) -> Self {
+        ConfigError::Json(err)
+    }
+}
+
+/// On-disk representation of a single guild's configuration.
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+struct GuildConfig {
+    /// SVar values, keyed by `SVar::get_key()`. Missing keys fall back to the SVar default.
+    #[serde(default)]
+    svars: HashMap<String, Value>,
+    /// Free-form per-guild state owned by individual subsystems, keyed by subsystem name.
+    #[serde(default)]
+    sections: HashMap<String, Value>,
+}
+
+/// Per-guild configuration store. Each guild is persisted to its own JSON file (`<guild id>.json`) in the storage
+/// directory, and is loaded lazily the first time it's accessed.
+pub struct ConfigStore {
+    root: PathBuf,
+    guilds: Mutex<HashMap<GuildId, GuildConfig>>,
+}
+
+impl ConfigStore {
+    /// Opens (creating if necessary) a configuration store rooted at the given directory.
+    pub fn new<P: Into<PathBuf>>(root: P) -> Result<ConfigStore, ConfigError> {
+        let root = root.into();
+        fs::create_dir_all(&root)?;
+        info!("Using guild configuration storage at {}", root.display());
+
+        Ok(ConfigStore {
+            root,
+            guilds: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Gets the value of an SVar for a guild, falling back to the SVar's default if unset or unreadable.
+    pub fn get<S: SVar>(&self, guild: GuildId) -> S::Target {
+        self.get_raw(guild, S::get_key())
+            .and_then(|val| match serde_json::from_value(val) {
+                Ok(val) => Some(val),
+                Err(err) => {
+                    warn!("Invalid stored value for SVar '{}' in guild {}: {}", S::get_key(), guild, err);
+                    None
+                }
+            })
+            .unwrap_or_else(S::get_default)
+    }
+
+    /// Sets the value of an SVar for a guild and persists it.
+    pub fn set<S: SVar>(&self, guild: GuildId, value: S::Target) -> Result<(), ConfigError> {
+        let value = serde_json::to_value(value)?;
+        self.set_raw(guild, S::get_key(), value)
+    }
+
+    /// Removes any stored value for an SVar, returning it to its default.
+    pub fn reset<S: SVar>(&self, guild: GuildId) -> Result<(), ConfigError> {
+        self.reset_raw(guild, S::get_key())
+    }
+
+    /// Gets the stored JSON value for an SVar key, if one has been set.
+    pub fn get_raw(&self, guild: GuildId, key: &str) -> Option<Value> {
+        self.with_guild(guild, |conf| conf.svars.get(key).cloned())
+    }
+
+    /// Sets the stored JSON value for an SVar key. No type checking is performed, but the value must satisfy the
+    /// SVar's constraints.
+    pub fn set_raw(&self, guild: GuildId, key: &str, value: Value) -> Result<(), ConfigError> {
+        self.modify(guild, |conf| {
+            validate(conf, key, &value)?;
+            conf.svars.insert(key.into(), value);
+            Ok(())
+        })
+    }
+
+    /// Removes the stored JSON value for an SVar key. The SVar's default must satisfy its constraints given the
+    /// guild's other values.
+    pub fn reset_raw(&self, guild: GuildId, key: &str) -> Result<(), ConfigError> {
+        self.modify(guild, |conf| {
+            if let Some(info) = svar::find(key) {
+                validate(conf, key, &(info.default)())?;
+            }
+            conf.svars.remove(key);
+            Ok(())
+        })
+    }
+
+    /// Gets a subsystem's section of a guild's configuration, or the default if it's missing or unreadable.
+    pub fn get_section<T: DeserializeOwned + Default>(&self, guild: GuildId, name: &str) -> T {
+        self.with_guild(guild, |conf| read_section(conf, guild, name))
+    }
+
+    /// Applies a change to a subsystem's section of a guild's configuration and persists it. If persisting fails,
+    /// the change is discarded.
+    pub fn update_section<T, R, F>(&self, guild: GuildId, name: &str, thunk: F) -> Result<R, ConfigError>
+    where
+        T: Serialize + DeserializeOwned + Default,
+        F: FnOnce(&mut T) -> R,
+    {
+        self.modify(guild, |conf| {
+            let mut section: T = read_section(conf, guild, name);
+            let res = thunk(&mut section);
+            conf.sections.insert(name.into(), serde_json::to_value(section)?);
+            Ok(res)
+        })
+    }
+
+    /// Lists every guild with stored configuration, including guilds which haven't been loaded yet.
+    pub fn guilds(&self) -> Vec<GuildId> {
+        let mut guilds = self.guilds.lock().keys().cloned().collect::<HashSet<_>>();
+
+        match fs::read_dir(&self.root) {
+            Ok(entries) => for entry in entries.filter_map(Result::ok) {
+                let path = entry.path();
+                if path.extension().map_or(true, |it| it != "json") {
+                    continue;
+                }
+                if let Some(id) = path.file_stem().and_then(|it| it.to_str()).and_then(|it| it.parse().ok()) {
+                    guilds.insert(GuildId(id));
+                }
+            },
+            Err(err) => warn!("Unable to list guild configuration in {}: {}", self.root.display(), err),
+        }
+
+        guilds.into_iter().collect()
+    }
+
+    fn path_for(&self, guild: GuildId) -> PathBuf {
+        self.root.join(format!("{}.json", guild.0))
+    }
+
+    fn with_guild<R, F: FnOnce(&GuildConfig) -> R>(&self, guild: GuildId, thunk: F) -> R {
+        let mut lock = self.guilds.lock();
+        let conf = lock.entry(guild).or_insert_with(|| self.load(guild));
+        thunk(conf)
+    }
+
+    /// Applies a change to a copy of the guild's configuration, only committing it in memory once it's on disk. If
+    /// the change itself fails, nothing is saved.
+    fn modify<R, F>(&self, guild: GuildId, thunk: F) -> Result<R, ConfigError>
+    where
+        F: FnOnce(&mut GuildConfig) -> Result<R, ConfigError>,
+    {
+        let mut lock = self.guilds.lock();
+        let conf = lock.entry(guild).or_insert_with(|| self.load(guild));
+
+        let mut updated = conf.clone();
+        let res = thunk(&mut updated)?;
+        self.save(guild, &updated)?;
+        *conf = updated;
+
+        Ok(res)
+    }
+
+    fn load(&self, guild: GuildId) -> GuildConfig {
+        let path = self.path_for(guild);
+        if !path.exists() {
+            return GuildConfig::default();
+        }
+
+        let res = File::open(&path)
+            .map_err(ConfigError::from)
+            .and_then(|file| serde_json::from_reader(BufReader::new(file)).map_err(ConfigError::from));
+
+        match res {
+            Ok(conf) => conf,
+            Err(err) => {
+                // Move the broken file aside rather than silently overwriting it on the next save.
+                error!("Unable to load configuration for guild {} ({}): {}", guild, path.display(), err);
+                let _ = fs::rename(&path, path.with_extension("json.broken"));
+                GuildConfig::default()
+            }
+        }
+    }
+
+    fn save(&self, guild: GuildId, conf: &GuildConfig) -> Result<(), ConfigError> {
+        // Write to a temporary file and rename over the original, so a crash mid-write can't truncate the config.
+        let path = self.path_for(guild);
+        let tmp_path = path.with_extension("json.tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp_path)?);
+            serde_json::to_writer_pretty(&mut writer, conf)?;
+            writer.flush()?;
+        }
+        fs::rename(&tmp_path, &path)?;
+
+        debug!("Saved configuration for guild {} to {}", guild, path.display());
+        Ok(())
+    }
+}
+
+fn read_section<T: DeserializeOwned + Default>(conf: &GuildConfig, guild: GuildId, name: &str) -> T {
+    conf.sections
+        .get(name)
+        .cloned()
+        .and_then(|val| match serde_json::from_value(val) {
+            Ok(val) => Some(val),
+            Err(err) => {
+                warn!("Invalid stored section '{}' in guild {}: {}", name, guild, err);
+                None
+            }
+        })
+        .unwrap_or_default()
+}
+
+/// Checks a candidate SVar value against the SVar's constraints, resolving other SVars from the guild's configuration.
+fn validate(conf: &GuildConfig, key: &str, value: &Value) -> Result<(), ConfigError> {
+    let info = match svar::find(key) {
+        Some(info) => info,
+        None => return Ok(()),
+    };
+
+    info.validate(value, |other| {
+        conf.svars
+            .get(other)
+            .cloned()
+            .or_else(|| svar::find(other).map(|it| (it.default)()))
+            .unwrap_or(Value::Null)
+    }).map_err(ConfigError::Invalid)
+}
@@ -0,0 +1,199 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
+
+use parking_lot::RwLock;
+use serenity::model::id::{GuildId, RoleId, UserId};
+
+use super::config::{ConfigError, ConfigStore};
+use super::svar::SVar;
+
+/// Name of the permissions section in each guild's configuration.
+const SECTION: &str = "permissions";
+
+/// Permission tiers, in ascending order of privilege.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum PermLevel {
+    /// Any user.
+    User,
+    /// Users granted superuser on a specific guild, either directly or through a role. Guild owners are always
+    /// superusers on their own guild.
+    Superuser,
+    /// Bot owners, as listed in `discord.owners`. Owners have every permission on every guild.
+    Owner,
+}
+
+impl fmt::Display for PermLevel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PermLevel::User => write!(f, "user"),
+            PermLevel::Superuser => write!(f, "superuser"),
+            PermLevel::Owner => write!(f, "bot owner"),
+        }
+    }
+}
+
+/// Something which can be granted superuser on a guild.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grantee {
+    User(UserId),
+    Role(RoleId),
+}
+
+/// Per-guild superuser grants, as persisted in the guild's configuration.
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct GuildPermissions {
+    #[serde(default)]
+    pub users: Vec<u64>,
+    #[serde(default)]
+    pub roles: Vec<u64>,
+}
+
+/// Determines the minimum permission level needed to run a command, given the guild it's being run in.
+pub type Requirement = fn(&ConfigStore, Option<GuildId>) -> PermLevel;
+
+/// Requirement for commands restricted to superusers and above.
+pub fn superuser(_: &ConfigStore, _: Option<GuildId>) -> PermLevel {
+    PermLevel::Superuser
+}
+
+/// Requirement for commands restricted to bot owners.
+pub fn owner(_: &ConfigStore, _: Option<GuildId>) -> PermLevel {
+    PermLevel::Owner
+}
+
+/// Requirement for commands which are available to all users unless the guild has disabled the given `AllowNormal*`
+/// SVar, in which case they're restricted to superusers.
+pub fn allow_normal_if<S: SVar<Target = bool>>(store: &ConfigStore, guild: Option<GuildId>) -> PermLevel {
+    match guild {
+        Some(guild) if !store.get::<S>(guild) => PermLevel::Superuser,
+        _ => PermLevel::User,
+    }
+}
+
+/// Requirement for commands which are restricted to bot owners, unless the guild has enabled the given SVar to also
+/// allow superusers.
+pub fn superuser_if<S: SVar<Target = bool>>(store: &ConfigStore, guild: Option<GuildId>) -> PermLevel {
+    match guild {
+        Some(guild) if store.get::<S>(guild) => PermLevel::Superuser,
+        _ => PermLevel::Owner,
+    }
+}
+
+/// Resolves users' permission levels and the levels required by commands.
+pub struct Permissions {
+    owners: HashSet<UserId>,
+    store: Arc<ConfigStore>,
+    requirements: RwLock<HashMap<String, Requirement>>,
+}
+
+impl Permissions {
+    pub fn new(owners: HashSet<UserId>, store: Arc<ConfigStore>) -> Permissions {
+        Permissions {
+            owners,
+            store,
+            requirements: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Returns true if the user is a bot owner.
+    pub fn is_owner(&self, user: UserId) -> bool {
+        self.owners.contains(&user)
+    }
+
+    /// Returns the set of bot owners.
+    pub fn owners(&self) -> &HashSet<UserId> {
+        &self.owners
+    }
+
+    /// Resolves a user's permission level in a guild (or in DMs, if no guild is given).
+    pub fn level(&self, user: UserId, guild: Option<GuildId>) -> PermLevel {
+        if self.is_owner(user) {
+            return PermLevel::Owner;
+        }
+
+        let guild = match guild {
+            Some(guild) => guild,
+            None => return PermLevel::User,
+        };
+
+        let perms: GuildPermissions = self.store.get_section(guild, SECTION);
+        if perms.users.contains(&user.0) {
+            return PermLevel::Superuser;
+        }
+
+        if let Some(cached) = guild.find() {
+            if cached.read().owner_id == user {
+                return PermLevel::Superuser;
+            }
+        }
+
+        if !perms.roles.is_empty() {
+            match guild.member(user) {
+                Ok(member) => {
+                    if member.roles.iter().any(|role| perms.roles.contains(&role.0)) {
+                        return PermLevel::Superuser;
+                    }
+                }
+                Err(err) => warn!("Unable to fetch member {} of guild {}: {:?}", user, guild, err),
+            }
+        }
+
+        PermLevel::User
+    }
+
+    /// Registers the permission requirement for a command, by its full name (including group prefix).
+    pub fn require(&self, cmd: &str, requirement: Requirement) {
+        self.requirements.write().insert(cmd.into(), requirement);
+    }
+
+    /// Resolves the permission level required to run a command in a guild. Commands without a registered
+    /// requirement are available to all users.
+    pub fn required_level(&self, cmd: &str, guild: Option<GuildId>) -> PermLevel {
+        match self.requirements.read().get(cmd) {
+            Some(requirement) => requirement(&self.store, guild),
+            None => PermLevel::User,
+        }
+    }
+
+    /// Returns true if the user may run the command in the guild.
+    pub fn can_run(&self, cmd: &str, user: UserId, guild: Option<GuildId>) -> bool {
+        self.level(user, guild) >= self.required_level(cmd, guild)
+    }
+
+    /// Returns the superuser grants for a guild.
+    pub fn grants(&self, guild: GuildId) -> GuildPermissions {
+        self.store.get_section(guild, SECTION)
+    }
+
+    /// Grants superuser on a guild. Returns false if the grant already existed.
+    pub fn grant(&self, guild: GuildId, grantee: Grantee) -> Result<bool, ConfigError> {
+        self.store.update_section(guild, SECTION, |perms: &mut GuildPermissions| {
+            let (list, id) = match grantee {
+                Grantee::User(id) => (&mut perms.users, id.0),
+                Grantee::Role(id) => (&mut perms.roles, id.0),
+            };
+
+            if list.contains(&id) {
+                false
+            } else {
+                list.push(id);
+                true
+            }
+        })
+    }
+
+    /// Revokes superuser on a guild. Returns false if there was no such grant.
+    pub fn revoke(&self, guild: GuildId, grantee: Grantee) -> Result<bool, ConfigError> {
+        self.store.update_section(guild, SECTION, |perms: &mut GuildPermissions| {
+            let (list, id) = match grantee {
+                Grantee::User(id) => (&mut perms.users, id.0),
+                Grantee::Role(id) => (&mut perms.roles, id.0),
+            };
+
+            let len = list.len();
+            list.retain(|it| *it != id);
+            list.len() != len
+        })
+    }
+}
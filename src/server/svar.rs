@@ -1,9 +1,16 @@
-use serde::Deserialize;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{self, Value};
+
+use super::permissions::PermLevel;
 
 /// An abstract representation of a server variable (SVar).
-pub trait SVar<'de> {
+pub trait SVar {
     /// The underlying type of this SVar. Typical values include `bool`, `String`, `i64` and `u64`.
-    type Target: Send + Sync + Deserialize<'de>;
+    type Target: Send + Sync + Serialize + DeserializeOwned;
 
     /// Returns the SVar's key as seen in the JSON config files.
     fn get_key() -> &'static str;
@@ -15,11 +22,127 @@ pub trait SVar<'de> {
     fn get_default() -> Self::Target;
 }
 
+/// Runtime description of an SVar, so SVars can be enumerated and edited without knowing their types statically.
+pub struct SVarInfo {
+    /// The SVar's key as seen in the JSON config files.
+    pub key: &'static str,
+    /// The SVar's human-readable name.
+    pub human: &'static str,
+    /// The name of the SVar's underlying type, for presenting to operators.
+    pub type_name: &'static str,
+    /// Produces the SVar's default value as JSON.
+    pub default: fn() -> Value,
+    /// Parses operator input into a JSON value of the SVar's underlying type.
+    pub parse: fn(&str) -> Result<Value, String>,
+    /// Constraints any new value must satisfy.
+    pub constraints: &'static [Constraint],
+    /// Permission level needed to change the SVar. Usually superuser, but SVars which grant superusers extra powers
+    /// may only be changed by bot owners.
+    pub level: PermLevel,
+}
+
+impl SVarInfo {
+    /// Checks a candidate value against all of this SVar's constraints. `lookup` provides the current value of other
+    /// SVars (by key) for cross-field constraints.
+    pub fn validate<F: Fn(&str) -> Value>(&self, value: &Value, lookup: F) -> Result<(), String> {
+        for constraint in self.constraints {
+            constraint.check(value, &lookup)?;
+        }
+        Ok(())
+    }
+}
+
+/// A restriction on the values an SVar may take.
+#[derive(Debug)]
+pub enum Constraint {
+    /// The value must be an integer within this inclusive range.
+    Range(i64, i64),
+    /// The value must be one of these strings.
+    OneOf(&'static [&'static str]),
+    /// The value must be strictly less than the value of the SVar with this key.
+    LessThan(&'static str),
+    /// The value must be strictly greater than the value of the SVar with this key.
+    GreaterThan(&'static str),
+}
+
+impl Constraint {
+    /// Checks a candidate value against this constraint, returning a user-presentable reason on failure.
+    pub fn check<F: Fn(&str) -> Value>(&self, value: &Value, lookup: F) -> Result<(), String> {
+        match *self {
+            Constraint::Range(min, max) => match value.as_i64() {
+                Some(val) if val >= min && val <= max => Ok(()),
+                _ => Err(format!("Value must be between {} and {}.", min, max)),
+            },
+            Constraint::OneOf(choices) => match value.as_str() {
+                Some(val) if choices.contains(&val) => Ok(()),
+                _ => Err(format!("Value must be one of: {}.", format_choices(choices))),
+            },
+            Constraint::LessThan(other) => match (value.as_i64(), lookup(other).as_i64()) {
+                (Some(val), Some(other_val)) if val < other_val => Ok(()),
+                (_, other_val) => Err(format!(
+                    "Value must be less than `{}` (currently {}).",
+                    other,
+                    other_val.map(|it| it.to_string()).unwrap_or_else(|| "unset".into())
+                )),
+            },
+            Constraint::GreaterThan(other) => match (value.as_i64(), lookup(other).as_i64()) {
+                (Some(val), Some(other_val)) if val > other_val => Ok(()),
+                (_, other_val) => Err(format!(
+                    "Value must be greater than `{}` (currently {}).",
+                    other,
+                    other_val.map(|it| it.to_string()).unwrap_or_else(|| "unset".into())
+                )),
+            },
+        }
+    }
+
+    /// Describes this constraint for presenting to operators.
+    pub fn describe(&self) -> String {
+        match *self {
+            Constraint::Range(min, max) => format!("Between {} and {}", min, max),
+            Constraint::OneOf(choices) => format!("One of: {}", format_choices(choices)),
+            Constraint::LessThan(other) => format!("Less than `{}`", other),
+            Constraint::GreaterThan(other) => format!("Greater than `{}`", other),
+        }
+    }
+}
+
+/// Lists the choices of a `OneOf` constraint as code spans, e.g. `` `full`, `summary` ``.
+fn format_choices(choices: &[&str]) -> String {
+    choices.iter().map(|it| format!("`{}`", it)).collect::<Vec<_>>().join(", ")
+}
+
+/// Looks up an SVar's runtime description by key.
+pub fn find(key: &str) -> Option<&'static SVarInfo> {
+    SVARS.iter().find(|it| it.key == key)
+}
+
+/// Serializes an SVar's default value. Used to build the SVar registry.
+pub fn default_value<S: SVar>() -> Value {
+    serde_json::to_value(S::get_default()).expect("SVar default must serialize")
+}
+
+/// Parses a string into an SVar's underlying type, then serializes it. Used to build the SVar registry.
+pub fn parse_value<S: SVar>(input: &str) -> Result<Value, String>
+where
+    S::Target: FromStr,
+    <S::Target as FromStr>::Err: Display,
+{
+    let parsed = input.trim().parse::<S::Target>().map_err(|err| err.to_string())?;
+    serde_json::to_value(parsed).map_err(|err| err.to_string())
+}
+
 /// One-shot macro for bulk defining SVar types. Accepts a name ident (which names the struct "SVar" + name), key name
 /// as in the config file, human-readable version of the key for presenting to users, the type this SVar represents
-/// (which must be Deserialize from Serde) and an expression that produces a default value of that type.
+/// (which must be Serialize and Deserialize from Serde, as well as FromStr) and an expression that produces a default
+/// value of that type, optionally followed by a list of `Constraint`s new values must satisfy and by `level = LEVEL`,
+/// the `PermLevel` needed to change it (superuser if omitted). Also produces `SVARS`, a registry of every defined SVar.
 macro_rules! define_svars {
-    ($(($name:ident, $key:expr, $human:expr, $type:ty, $default:expr)),+$(,)*) => {
+    ($((
+        $name:ident, $key:expr, $human:expr, $type:ty, $default:expr
+        $(, [$($constraint:expr),*$(,)*])*
+        $(, level = $level:expr)*
+    )),+$(,)*) => {
         // Build mashup replacement macro
         mashup! {
             $(
@@ -41,7 +164,7 @@ macro_rules! define_svars {
                     /// The human-readable key represented by this type.
                     pub const HUMAN: &'static str = $human;
                 }
-                impl<'de> SVar<'de> for "@@" $name {
+                impl SVar for "@@" $name {
                     type Target = $type;
                     fn get_key() -> &'static str { "@@" $name::KEY }
                     fn get_human() -> &'static str { "@@" $name::HUMAN }
@@ -49,6 +172,34 @@ macro_rules! define_svars {
                 }
             }
         )+
+
+        // Build the runtime registry.
+        svar_mash! {
+            /// Every SVar defined in this module, in definition order.
+            pub static SVARS: &'static [SVarInfo] = &[
+                $(
+                    SVarInfo {
+                        key: $key,
+                        human: $human,
+                        type_name: stringify!($type),
+                        default: default_value::<"@@" $name>,
+                        parse: parse_value::<"@@" $name>,
+                        constraints: &[$($($constraint,)*)*],
+                        level: svar_level!($($level)*),
+                    },
+                )+
+            ];
+        }
+    };
+}
+
+/// Resolves the optional `level` of an SVar in `define_svars!`.
+macro_rules! svar_level {
+    () => {
+        PermLevel::Superuser
+    };
+    ($level:expr) => {
+        $level
     };
 }
 
@@ -57,7 +208,7 @@ define_svars!(
     (
         AllowNormalCensus,
         "census_allow_normal_users",
-        "~~Allow all users to use `!wow census`~~",
+        "Allow all users to use `!wow census`",
         bool,
         true
     ),
@@ -68,60 +219,109 @@ define_svars!(
         bool,
         true
     ),
+    (
+        CondenserAllowIpHosts,
+        "condenser_allow_ip_hosts",
+        "Allow `!shorten` to link to IP addresses",
+        bool,
+        false
+    ),
+    (
+        CondenserAllowShortLinks,
+        "condenser_allow_short_links",
+        "Allow `!shorten` to link to other URL shorteners",
+        bool,
+        false
+    ),
+    // Shortened URLs are shown in embed fields, which Discord limits to 1024 characters.
+    (
+        CondenserMaxUrlLength,
+        "condenser_max_url_length",
+        "Longest URL `!shorten` will accept",
+        i64,
+        1000i64,
+        [Constraint::Range(16, 1000)]
+    ),
+    (
+        CondenserQr,
+        "condenser_qr",
+        "Always attach a QR code to `!shorten` results",
+        bool,
+        false
+    ),
     (
         AllowNormalShowme,
         "showme_allow_normal_users",
-        "~~Allow all users to use `!wow showme`~~",
+        "Allow all users to use `!wow showme`",
         bool,
         true
     ),
     (
         AllowNormalQuotes,
         "quotes_allow_normal_user",
-        "~~Allow all users to use `!quotes`~~",
+        "Allow all users to use `!quotes`",
         bool,
         true
     ),
     (
         RmHistAllowSu,
         "rmhist_allow_su",
-        "~~Allow superusers to use !rmhist~~",
+        "Allow superusers to use `!rmhist`",
         bool,
-        true
+        true,
+        level = PermLevel::Owner
     ),
     (
         UseSnark,
         "snark_enabled",
-        "~~Enable snarky responses~~",
+        "Enable snarky responses",
         bool,
         false
     ),
     (
         UseGames,
         "games_enabled",
-        "~~Enable games commands such as `!roll`~~",
+        "Enable games commands such as `!roll`",
         bool,
         true
     ),
     (
         RollMin,
         "roll_min",
-        "~~Default minimum for `!roll`~~",
+        "Default minimum for `!roll`",
         i64,
-        1i64
+        1i64,
+        [Constraint::Range(-1_000_000, 1_000_000), Constraint::LessThan("roll_max")]
     ),
     (
         RollMax,
         "roll_max",
-        "~~Default maximum for `!roll`~~",
+        "Default maximum for `!roll`",
         i64,
-        100i64
+        100i64,
+        [Constraint::Range(-1_000_000, 1_000_000), Constraint::GreaterThan("roll_min")]
+    ),
+    (
+        RollDetail,
+        "roll_detail",
+        "How `!roll` shows dice: `full` lists every die, `summary` only each group's total",
+        String,
+        "full".to_string(),
+        [Constraint::OneOf(&["full", "summary"])]
     ),
     (
         DiscAllowSu,
         "disc_allow_su",
-        "~~Allow superusers to use disciplinary commands~~",
+        "Allow superusers to use disciplinary commands",
         bool,
-        false
+        false,
+        level = PermLevel::Owner
+    ),
+    (
+        MuteRole,
+        "mute_role",
+        "Name of the role given to muted members",
+        String,
+        "Muted".to_string()
     ),
 );
@@ -0,0 +1,194 @@
+//! Canned snarky responses to trigger phrases and repeated failed commands, loaded from a JSON data file. See
+//! `snark.json.example` for the file format.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use rand::{self, Rng};
+use regex::{self, Regex, RegexBuilder};
+use serde_json;
+use serenity::model::id::{GuildId, UserId};
+
+lazy_static! {
+    static ref MENTION_REGEX: Regex = Regex::new(r"<@[!&]?\d+>").unwrap();
+}
+
+/// Errors which can occur when loading snark rules.
+#[derive(Debug)]
+pub enum SnarkError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// A trigger's pattern isn't a valid regex.
+    Pattern(regex::Error),
+}
+
+impl fmt::Display for SnarkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SnarkError::Io(ref err) => write!(f, "I/O error: {}", err),
+            SnarkError::Json(ref err) => write!(f, "JSON error: {}", err),
+            SnarkError::Pattern(ref err) => write!(f, "invalid trigger pattern: {}", err),
+        }
+    }
+}
+
+impl From<io::Error> for SnarkError {
+    fn from(err: io::Error) -> SnarkError {
+        SnarkError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SnarkError {
+    fn from(err: serde_json::Error) -> SnarkError {
+        SnarkError::Json(err)
+    }
+}
+
+fn default_cooldown() -> u64 {
+    120
+}
+
+#[derive(Deserialize, Debug)]
+struct SnarkFile {
+    /// Minimum time between responses in the same guild.
+    #[serde(default = "default_cooldown")]
+    cooldown_seconds: u64,
+    #[serde(default)]
+    triggers: Vec<TriggerDef>,
+    failed_commands: Option<FailedCommands>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TriggerDef {
+    /// Case-insensitive regex, matched against the message with any mentions removed.
+    pattern: String,
+    /// Only fire if the message mentions the bot.
+    #[serde(default)]
+    mention_only: bool,
+    responses: Vec<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct FailedCommands {
+    /// Number of failed commands from one user which triggers a response.
+    threshold: usize,
+    /// Period over which failures are counted.
+    window_seconds: u64,
+    responses: Vec<String>,
+}
+
+struct Trigger {
+    pattern: Regex,
+    mention_only: bool,
+    responses: Vec<String>,
+}
+
+/// Decides when and how to be snarky. Callers are responsible for checking the guild has enabled snark.
+pub struct SnarkEngine {
+    cooldown: Duration,
+    triggers: Vec<Trigger>,
+    failed_commands: Option<FailedCommands>,
+    last_response: Mutex<HashMap<GuildId, Instant>>,
+    failures: Mutex<HashMap<(GuildId, UserId), Vec<Instant>>>,
+}
+
+impl SnarkEngine {
+    /// Creates an engine with no rules, which never responds.
+    pub fn empty() -> SnarkEngine {
+        SnarkEngine::from_file(SnarkFile {
+            cooldown_seconds: default_cooldown(),
+            triggers: Vec::new(),
+            failed_commands: None,
+        }).expect("empty snark rules are valid")
+    }
+
+    /// Loads rules from a JSON data file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SnarkEngine, SnarkError> {
+        let reader = BufReader::new(File::open(path)?);
+        SnarkEngine::from_file(serde_json::from_reader(reader)?)
+    }
+
+    /// Loads rules from a JSON string.
+    pub fn from_json(json: &str) -> Result<SnarkEngine, SnarkError> {
+        SnarkEngine::from_file(serde_json::from_str(json)?)
+    }
+
+    fn from_file(file: SnarkFile) -> Result<SnarkEngine, SnarkError> {
+        let triggers = file.triggers
+            .into_iter()
+            .filter(|it| !it.responses.is_empty())
+            .map(|it| {
+                let pattern = RegexBuilder::new(&it.pattern)
+                    .case_insensitive(true)
+                    .build()
+                    .map_err(SnarkError::Pattern)?;
+                Ok(Trigger {
+                    pattern,
+                    mention_only: it.mention_only,
+                    responses: it.responses,
+                })
+            })
+            .collect::<Result<Vec<_>, SnarkError>>()?;
+
+        Ok(SnarkEngine {
+            cooldown: Duration::from_secs(file.cooldown_seconds),
+            triggers,
+            failed_commands: file.failed_commands.filter(|it| it.threshold > 0 && !it.responses.is_empty()),
+            last_response: Mutex::new(HashMap::new()),
+            failures: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Returns a response to a message, if it matches a trigger and the guild isn't on cooldown.
+    pub fn message(&self, guild_id: GuildId, content: &str, mentions_bot: bool, now: Instant) -> Option<String> {
+        let content = MENTION_REGEX.replace_all(content, "");
+        let content = content.trim();
+
+        let trigger = self.triggers
+            .iter()
+            .find(|it| (mentions_bot || !it.mention_only) && it.pattern.is_match(content))?;
+        self.respond(guild_id, &trigger.responses, now)
+    }
+
+    /// Records a failed command, returning a response once a user has failed enough commands in a short period.
+    pub fn command_failed(&self, guild_id: GuildId, user_id: UserId, now: Instant) -> Option<String> {
+        let rules = self.failed_commands.as_ref()?;
+        let window = Duration::from_secs(rules.window_seconds);
+
+        {
+            let mut failures = self.failures.lock();
+            let times = failures.entry((guild_id, user_id)).or_insert_with(Vec::new);
+            times.retain(|it| now.duration_since(*it) < window);
+            times.push(now);
+            if times.len() < rules.threshold {
+                return None;
+            }
+            times.clear();
+        }
+
+        self.respond(guild_id, &rules.responses, now)
+    }
+
+    /// Forgets a user's failed commands once they get one right.
+    pub fn command_succeeded(&self, guild_id: GuildId, user_id: UserId) {
+        self.failures.lock().remove(&(guild_id, user_id));
+    }
+
+    /// Picks a random response, unless the guild is on cooldown.
+    fn respond(&self, guild_id: GuildId, responses: &[String], now: Instant) -> Option<String> {
+        let mut last_response = self.last_response.lock();
+        if let Some(last) = last_response.get(&guild_id) {
+            if now.duration_since(*last) < self.cooldown {
+                return None;
+            }
+        }
+
+        let response = rand::thread_rng().choose(responses)?.clone();
+        last_response.insert(guild_id, now);
+        Some(response)
+    }
+}
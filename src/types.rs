@@ -3,9 +3,34 @@ use std::sync::Arc;
 use config;
 use typemap;
 
+use server::config::ConfigStore;
+use server::permissions::Permissions;
+use snark::SnarkEngine;
+
 // Newtype around Config to support ShareMap
 pub struct ConfigMarker;
 
 impl typemap::Key for ConfigMarker {
     type Value = Arc<config::Config>;
 }
+
+// Newtype around the per-guild ConfigStore to support ShareMap
+pub struct GuildConfigMarker;
+
+impl typemap::Key for GuildConfigMarker {
+    type Value = Arc<ConfigStore>;
+}
+
+// Newtype around Permissions to support ShareMap
+pub struct PermissionsMarker;
+
+impl typemap::Key for PermissionsMarker {
+    type Value = Arc<Permissions>;
+}
+
+// Newtype around the SnarkEngine to support ShareMap
+pub struct SnarkMarker;
+
+impl typemap::Key for SnarkMarker {
+    type Value = Arc<SnarkEngine>;
+}
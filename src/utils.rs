@@ -1,27 +1,33 @@
 use std::sync::Arc;
 
+use chrono::Duration;
+use image::png::PNGEncoder;
+use image::{ColorType, Luma};
+use qrcode::QrCode;
 use serenity::builder::CreateEmbed;
 use serenity::framework::standard::CommandOptions;
 use serenity::model::channel::Message;
-use serenity::model::id::ChannelId;
+use serenity::model::id::{ChannelId, GuildId};
 
 use constants::COLOUR_ERROR;
 
 pub fn usage_error_embed(cmd_name: &str, err_text: &str, opts: Arc<CommandOptions>, msg: &Message) {
-    let _ = msg.channel_id.send_message(|m| {
-        m.embed(|mut e| {
-            e = e.title("Error").description(err_text).colour(*COLOUR_ERROR);
-
-            if let Some(ref usage) = opts.usage {
-                e = e.field("Usage", format!("`!{} {}`", cmd_name, usage), false);
-            }
-            if let Some(ref example) = opts.example {
-                e = e.field("Example", format!("`!{} {}`", cmd_name, example), false);
-            }
-
-            e
-        })
-    });
+    let _ = msg.channel_id
+        .send_message(|m| m.embed(|e| usage_error(e, cmd_name, err_text, &opts)));
+}
+
+/// Fills in the embed sent by `usage_error_embed`: the error, followed by the command's usage and example.
+pub fn usage_error(mut e: CreateEmbed, cmd_name: &str, err_text: &str, opts: &CommandOptions) -> CreateEmbed {
+    e = e.title("Error").description(err_text).colour(*COLOUR_ERROR);
+
+    if let Some(ref usage) = opts.usage {
+        e = e.field("Usage", format!("`!{} {}`", cmd_name, usage), false);
+    }
+    if let Some(ref example) = opts.example {
+        e = e.field("Example", format!("`!{} {}`", cmd_name, example), false);
+    }
+
+    e
 }
 
 pub fn error_embed<T: FnOnce(CreateEmbed) -> (CreateEmbed)>(
@@ -39,6 +45,86 @@ pub fn error_embed<T: FnOnce(CreateEmbed) -> (CreateEmbed)>(
     });
 }
 
+/// Pulls the guild ID out of a message, or reports that the command must be run in a guild.
+pub fn require_guild(msg: &Message) -> Option<GuildId> {
+    let guild_id = msg.guild_id();
+    if guild_id.is_none() {
+        error_embed(&msg.channel_id, "This command can only be used in a server.", None, |e| e);
+    }
+    guild_id
+}
+
+/// Shortens text to at most `max` characters, marking where it was cut with an ellipsis.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+
+    let mut cut = text.chars().take(max.saturating_sub(1)).collect::<String>();
+    cut.push('…');
+    cut
+}
+
+/// Renders `data` as a QR code in PNG format. Returns None if the data is too long to fit in a QR code.
+pub fn qr_png(data: &str) -> Option<Vec<u8>> {
+    let code = QrCode::new(data.as_bytes()).ok()?;
+    let image = code.render::<Luma<u8>>().min_dimensions(256, 256).build();
+    let (width, height) = image.dimensions();
+
+    let mut png = Vec::new();
+    PNGEncoder::new(&mut png)
+        .encode(&image.into_raw(), width, height, ColorType::Gray(8))
+        .ok()?;
+    Some(png)
+}
+
+/// Parses a duration such as `30m`, `12h` or `1d12h`. Units are `s`, `m`, `h`, `d` and `w`.
+pub fn parse_duration(input: &str) -> Option<Duration> {
+    let mut total = Duration::zero();
+    let mut number = String::new();
+
+    for ch in input.chars() {
+        if ch.is_ascii_digit() {
+            number.push(ch);
+            continue;
+        }
+
+        let value = number.parse::<i64>().ok().filter(|it| *it <= 10_000)?;
+        number.clear();
+        total = total + match ch {
+            's' => Duration::seconds(value),
+            'm' => Duration::minutes(value),
+            'h' => Duration::hours(value),
+            'd' => Duration::days(value),
+            'w' => Duration::weeks(value),
+            _ => return None,
+        };
+    }
+
+    if !number.is_empty() || total <= Duration::zero() {
+        return None;
+    }
+    Some(total)
+}
+
+/// Formats a duration in the largest whole units, e.g. `1d 12h`.
+pub fn format_duration(duration: Duration) -> String {
+    let mut secs = duration.num_seconds();
+    let mut parts = Vec::new();
+    for &(unit, size) in &[("d", 86_400), ("h", 3_600), ("m", 60), ("s", 1)] {
+        if secs >= size {
+            parts.push(format!("{}{}", secs / size, unit));
+            secs %= size;
+        }
+    }
+
+    if parts.is_empty() {
+        "0s".into()
+    } else {
+        parts.join(" ")
+    }
+}
+
 /// Helper macro to make getting configuration references less messy.
 macro_rules! conf {
     ($cdata:ident) => {{
@@ -48,3 +134,23 @@ macro_rules! conf {
             .clone()
     }};
 }
+
+/// Helper macro to make getting the per-guild configuration store less messy.
+macro_rules! guild_conf {
+    ($cdata:expr) => {{
+        let lock = $cdata.lock();
+        lock.get::<GuildConfigMarker>()
+            .expect("unable to load guild config store")
+            .clone()
+    }};
+}
+
+/// Helper macro to make getting the permissions resolver less messy.
+macro_rules! perms {
+    ($cdata:expr) => {{
+        let lock = $cdata.lock();
+        lock.get::<PermissionsMarker>()
+            .expect("unable to load permissions")
+            .clone()
+    }};
+}
@@ -0,0 +1,105 @@
+extern crate drakonid;
+extern crate futures;
+extern crate hyper;
+
+mod common;
+
+use hyper::{Method, StatusCode};
+
+use drakonid::battlenet::{self, BattleNetClient, BattleNetError};
+
+const CHARACTER_JSON: &str = r#"{
+    "name": "Arkan",
+    "realm": "Argent Dawn",
+    "class": 8,
+    "race": 1,
+    "level": 110,
+    "thumbnail": "argent-dawn/12/3456-avatar.jpg",
+    "guild": { "name": "Drakon", "realm": "Argent Dawn" },
+    "items": { "averageItemLevel": 352, "averageItemLevelEquipped": 350 },
+    "talents": [
+        { "selected": true, "spec": { "name": "Frost", "role": "DPS" } },
+        { "spec": { "name": "Fire", "role": "DPS" } }
+    ]
+}"#;
+
+#[test]
+fn character_lookup() {
+    let base = common::serve(|req| {
+        assert_eq!(req.method, Method::Get);
+        assert_eq!(req.path, "/wow/character/Argent%20Dawn/Arkan");
+        let query = req.query.unwrap_or_default();
+        assert!(query.contains("apikey=TOKEN"));
+        assert!(query.contains("fields=guild%2Citems%2Ctalents"));
+        (StatusCode::Ok, CHARACTER_JSON.into())
+    });
+
+    let client = BattleNetClient::new(&base, "TOKEN");
+    let profile = client.character("eu", "Argent Dawn", "Arkan").expect("character lookup");
+
+    assert_eq!(profile.name, "Arkan");
+    assert_eq!(profile.level, 110);
+    assert_eq!(battlenet::class_name(profile.class), "Mage");
+    assert_eq!(profile.active_spec().map(|it| it.name.as_str()), Some("Frost"));
+    assert_eq!(profile.items.map(|it| it.average_item_level_equipped), Some(350));
+    assert_eq!(profile.guild.map(|it| it.name), Some("Drakon".to_string()));
+}
+
+#[test]
+fn character_not_found() {
+    let base = common::serve(|_| (StatusCode::NotFound, r#"{"status":"nok"}"#.into()));
+    let client = BattleNetClient::new(&base, "TOKEN");
+
+    match client.character("us", "Nowhere", "Nobody") {
+        Err(BattleNetError::NotFound) => {}
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+}
+
+#[test]
+fn bad_api_key() {
+    let base = common::serve(|_| (StatusCode::Forbidden, String::new()));
+    let client = BattleNetClient::new(&base, "BAD");
+
+    match client.character("us", "Argent Dawn", "Arkan") {
+        Err(BattleNetError::Unauthorized) => {}
+        other => panic!("expected Unauthorized, got {:?}", other),
+    }
+}
+
+#[test]
+fn guild_census() {
+    let base = common::serve(|req| {
+        assert_eq!(req.path, "/wow/guild/Argent%20Dawn/Drakon");
+        (
+            StatusCode::Ok,
+            r#"{
+                "name": "Drakon",
+                "realm": "Argent Dawn",
+                "members": [
+                    { "character": { "name": "A", "class": 8, "race": 1, "level": 110 }, "rank": 0 },
+                    { "character": { "name": "B", "class": 8, "race": 4, "level": 110 }, "rank": 1 },
+                    { "character": { "name": "C", "class": 1, "race": 1, "level": 45 }, "rank": 1 }
+                ]
+            }"#.into(),
+        )
+    });
+
+    let client = BattleNetClient::new(&base, "TOKEN");
+    let census = client.guild_roster("eu", "Argent Dawn", "Drakon").expect("guild lookup").census();
+
+    assert_eq!(census.total, 3);
+    assert_eq!(census.classes, vec![("Mage", 2), ("Warrior", 1)]);
+    assert_eq!(census.races, vec![("Human", 2), ("Night Elf", 1)]);
+    assert_eq!(census.levels, vec![(110, 2), (40, 1)]);
+    assert_eq!(census.ranks, vec![(1, 2), (0, 1)]);
+}
+
+#[test]
+fn armory_links() {
+    assert_eq!(
+        battlenet::armory_url("eu", "Twisting Nether", "Arkan"),
+        "https://worldofwarcraft.com/en-gb/character/twisting-nether/arkan"
+    );
+    assert_eq!(battlenet::realm_slug("Mal'Ganis"), "malganis");
+}
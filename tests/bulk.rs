@@ -0,0 +1,65 @@
+extern crate drakonid;
+
+use drakonid::bulk::{csv_row, parse, BulkEntry};
+
+fn entry(line: usize, url: &str, code: Option<&str>) -> BulkEntry {
+    BulkEntry {
+        line,
+        url: url.into(),
+        code: code.map(String::from),
+    }
+}
+
+#[test]
+fn plain_urls() {
+    let entries = parse("https://example.com/\n\nhttps://example.org/\n");
+    assert_eq!(
+        entries,
+        vec![entry(1, "https://example.com/", None), entry(3, "https://example.org/", None)]
+    );
+}
+
+#[test]
+fn csv_with_header() {
+    let input = "url,code\r\nhttps://example.com/,ex\r\n\"https://example.org/\", \"org\"\r\nhttps://example.net/,\r\n";
+    let entries = parse(input);
+    assert_eq!(
+        entries,
+        vec![
+            entry(2, "https://example.com/", Some("ex")),
+            entry(3, "https://example.org/", Some("org")),
+            entry(4, "https://example.net/", None),
+        ]
+    );
+}
+
+#[test]
+fn comments() {
+    let entries = parse("# Event links\nhttps://example.com/ , poster\n");
+    assert_eq!(entries, vec![entry(2, "https://example.com/", Some("poster"))]);
+}
+
+#[test]
+fn header_after_comments() {
+    let entries = parse("# Event links\n\nURL,Code\nhttps://example.com/,ex\n");
+    assert_eq!(entries, vec![entry(4, "https://example.com/", Some("ex"))]);
+}
+
+#[test]
+fn quoted_commas() {
+    let input = "\"https://example.com/?a=1,2\",ex\n\"https://example.org/?q=\"\"hi\"\"\",\"org\",ignored\n";
+    let entries = parse(input);
+    assert_eq!(
+        entries,
+        vec![
+            entry(1, "https://example.com/?a=1,2", Some("ex")),
+            entry(2, "https://example.org/?q=\"hi\"", Some("org")),
+        ]
+    );
+}
+
+#[test]
+fn csv_quoting() {
+    assert_eq!(csv_row(&["1", "https://example.com/", "EX"]), "1,https://example.com/,EX\r\n");
+    assert_eq!(csv_row(&["a,b", "say \"hi\""]), "\"a,b\",\"say \"\"hi\"\"\"\r\n");
+}
@@ -0,0 +1,82 @@
+//! A tiny hyper-based stand-in server for testing HTTP API clients.
+#![allow(dead_code)]
+
+use std::net::SocketAddr;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+use futures::{Future, Stream};
+use hyper;
+use hyper::server::{Http, Request, Response, Service};
+use hyper::{Headers, Method, StatusCode};
+
+/// A request received by the mock server.
+pub struct MockRequest {
+    pub method: Method,
+    pub path: String,
+    pub query: Option<String>,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+}
+
+impl MockRequest {
+    /// Returns the body as a string.
+    pub fn body_str(&self) -> String {
+        String::from_utf8_lossy(&self.body).into_owned()
+    }
+}
+
+type Handler = Fn(MockRequest) -> (StatusCode, String) + Send + Sync;
+
+struct MockService {
+    handler: Arc<Handler>,
+}
+
+impl Service for MockService {
+    type Request = Request;
+    type Response = Response;
+    type Error = hyper::Error;
+    type Future = Box<Future<Item = Response, Error = hyper::Error>>;
+
+    fn call(&self, req: Request) -> Self::Future {
+        let handler = Arc::clone(&self.handler);
+        let (method, uri, _, headers, body) = req.deconstruct();
+
+        Box::new(body.concat2().map(move |chunk| {
+            let (status, body) = handler(MockRequest {
+                method,
+                path: uri.path().to_string(),
+                query: uri.query().map(|it| it.to_string()),
+                headers,
+                body: chunk.to_vec(),
+            });
+            Response::new().with_status(status).with_body(body)
+        }))
+    }
+}
+
+/// Starts a mock server on an ephemeral local port, returning its base URL (with a trailing slash). Every request is
+/// answered by `handler` with a status code and body. The server runs until the test process exits.
+pub fn serve<F>(handler: F) -> String
+where
+    F: Fn(MockRequest) -> (StatusCode, String) + Send + Sync + 'static,
+{
+    let handler: Arc<Handler> = Arc::new(handler);
+    let (tx, rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        let addr: SocketAddr = "127.0.0.1:0".parse().unwrap();
+        let server = Http::new()
+            .bind(&addr, move || {
+                Ok(MockService {
+                    handler: Arc::clone(&handler),
+                })
+            })
+            .expect("mock server bind");
+        tx.send(server.local_addr().expect("mock server address")).unwrap();
+        server.run().expect("mock server run");
+    });
+
+    format!("http://{}/", rx.recv().expect("mock server startup"))
+}
@@ -0,0 +1,454 @@
+extern crate drakonid;
+extern crate futures;
+extern crate hyper;
+extern crate url;
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use hyper::{Method, StatusCode};
+use url::Url;
+
+use drakonid::condenser::{resolve_server, CircuitBreaker, CondenserClient, CondenserError, RetryPolicy, ServerConf};
+
+fn client(base: &str, key: Option<&str>) -> CondenserClient {
+    CondenserClient::new(Url::parse(base).unwrap(), key.map(String::from))
+}
+
+fn fast_retry(attempts: u32) -> RetryPolicy {
+    RetryPolicy {
+        attempts,
+        initial_delay: Duration::from_millis(1),
+        max_delay: Duration::from_millis(5),
+    }
+}
+
+/// Serves a code's metadata, failing with 503 for the first `failures` requests. Returns the base URL and a counter
+/// of requests received.
+fn flaky_meta(failures: usize) -> (String, Arc<AtomicUsize>) {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&hits);
+    let base = common::serve(move |_| {
+        if counter.fetch_add(1, Ordering::SeqCst) < failures {
+            return (StatusCode::ServiceUnavailable, String::new());
+        }
+        (
+            StatusCode::Ok,
+            r#"{"full_url":"https://example.com/","meta":{"owner":"drakonid","time":"2018-06-01T12:00:00+00:00"}}"#
+                .into(),
+        )
+    });
+    (base, hits)
+}
+
+#[test]
+fn shorten() {
+    let base = common::serve(|req| {
+        assert_eq!(req.method, Method::Post);
+        assert_eq!(req.path, "/api/shorten");
+        assert_eq!(req.headers.get_raw("X-API-Key").and_then(|it| it.one()), Some(&b"KEY"[..]));
+        let body = req.body_str();
+        assert!(body.contains(r#""url":"https://example.com/""#));
+        assert!(body.contains(r#""code":"EX""#));
+        (StatusCode::Ok, r#"{"short_url":"http://short.test/EX"}"#.into())
+    });
+
+    let short_url = client(&base, Some("KEY"))
+        .shorten("https://example.com/", Some("EX"), Some("meta"))
+        .expect("shorten");
+    assert_eq!(short_url.as_str(), "http://short.test/EX");
+}
+
+#[test]
+fn shorten_conflict() {
+    let base = common::serve(|_| (StatusCode::Conflict, String::new()));
+
+    match client(&base, Some("KEY")).shorten("https://example.com/", Some("EX"), None) {
+        Err(CondenserError::Conflict) => {}
+        other => panic!("expected Conflict, got {:?}", other),
+    }
+}
+
+#[test]
+fn shorten_without_key() {
+    // No request should be made at all.
+    let base = common::serve(|_| panic!("unexpected request"));
+
+    match client(&base, None).shorten("https://example.com/", None, None) {
+        Err(CondenserError::Unauthorized) => {}
+        other => panic!("expected Unauthorized, got {:?}", other),
+    }
+}
+
+#[test]
+fn bad_api_key() {
+    let base = common::serve(|_| (StatusCode::Unauthorized, String::new()));
+
+    match client(&base, Some("BAD")).delete("EX") {
+        Err(CondenserError::Unauthorized) => {}
+        other => panic!("expected Unauthorized, got {:?}", other),
+    }
+}
+
+#[test]
+fn meta() {
+    let base = common::serve(|req| {
+        assert_eq!(req.method, Method::Get);
+        assert_eq!(req.path, "/api/meta/EX");
+        (
+            StatusCode::Ok,
+            r#"{
+                "full_url": "https://example.com/",
+                "meta": {
+                    "owner": "drakonid",
+                    "time": "2018-06-01T12:00:00+00:00",
+                    "user_meta": "<1234> Submitted via Drakonid by Arkan#0001 (via PM)"
+                }
+            }"#.into(),
+        )
+    });
+
+    let meta = client(&base, None).meta("EX").expect("meta");
+    assert_eq!(meta.full_url.as_str(), "https://example.com/");
+    assert_eq!(meta.meta.owner, "drakonid");
+    assert!(meta.meta.user_meta.unwrap().contains("<1234>"));
+    assert!(meta.stats.is_none());
+}
+
+#[test]
+fn meta_stats() {
+    let base = common::serve(|_| {
+        (
+            StatusCode::Ok,
+            r#"{
+                "full_url": "https://example.com/",
+                "meta": {"owner": "drakonid", "time": "2018-06-01T12:00:00+00:00"},
+                "stats": {
+                    "hits": 42,
+                    "last_accessed": "2018-06-03T08:30:00+00:00",
+                    "daily_hits": [0, 7, 14, 21]
+                }
+            }"#.into(),
+        )
+    });
+
+    let stats = client(&base, None).meta("EX").expect("meta").stats.expect("stats");
+    assert_eq!(stats.hits, 42);
+    assert!(stats.last_accessed.is_some());
+    assert_eq!(stats.sparkline(30).unwrap(), "▁▃▅█");
+    assert_eq!(stats.sparkline(2).unwrap(), "▅█");
+}
+
+#[test]
+fn meta_partial_stats() {
+    let base = common::serve(|_| {
+        (
+            StatusCode::Ok,
+            r#"{
+                "full_url": "https://example.com/",
+                "meta": {"owner": "drakonid", "time": "2018-06-01T12:00:00+00:00"},
+                "stats": {"hits": 3}
+            }"#.into(),
+        )
+    });
+
+    let stats = client(&base, None).meta("EX").expect("meta").stats.expect("stats");
+    assert_eq!(stats.hits, 3);
+    assert!(stats.last_accessed.is_none());
+    assert!(stats.sparkline(30).is_none());
+}
+
+#[test]
+fn meta_not_found() {
+    let base = common::serve(|_| (StatusCode::NotFound, String::new()));
+
+    match client(&base, None).meta("NOPE") {
+        Err(CondenserError::NotFound) => {}
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+}
+
+#[test]
+fn meta_bad_response() {
+    let base = common::serve(|_| (StatusCode::Ok, "not json".into()));
+
+    match client(&base, None).meta("EX") {
+        Err(CondenserError::Parse(_)) => {}
+        other => panic!("expected Parse, got {:?}", other),
+    }
+}
+
+#[test]
+fn edit() {
+    let base = common::serve(|req| {
+        assert_eq!(req.method, Method::Post);
+        assert_eq!(req.path, "/api/edit");
+        let body = req.body_str();
+        assert!(body.contains(r#""code":"EX""#));
+        assert!(body.contains(r#""url":"https://example.org/""#));
+        assert!(body.contains(r#""meta":"note""#));
+        (StatusCode::Ok, r#"{"code":"EX","status":"ok"}"#.into())
+    });
+
+    client(&base, Some("KEY")).edit("EX", "https://example.org/", Some("note")).expect("edit");
+}
+
+#[test]
+fn edit_missing() {
+    let base = common::serve(|_| (StatusCode::Ok, r#"{"code":"EX","status":"noexist"}"#.into()));
+
+    match client(&base, Some("KEY")).edit("EX", "https://example.org/", None) {
+        Err(CondenserError::NotFound) => {}
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+}
+
+#[test]
+fn delete() {
+    let base = common::serve(|req| {
+        assert_eq!(req.method, Method::Post);
+        assert_eq!(req.path, "/api/delete");
+        assert!(req.body_str().contains(r#""code":"EX""#));
+        (StatusCode::Ok, r#"{"code":"EX","status":"ok"}"#.into())
+    });
+
+    client(&base, Some("KEY")).delete("EX").expect("delete");
+}
+
+#[test]
+fn delete_missing() {
+    let base = common::serve(|_| (StatusCode::Ok, r#"{"code":"EX","status":"noexist"}"#.into()));
+
+    match client(&base, Some("KEY")).delete("EX") {
+        Err(CondenserError::NotFound) => {}
+        other => panic!("expected NotFound, got {:?}", other),
+    }
+}
+
+#[test]
+fn transport_error() {
+    // Nothing listens on port 1.
+    match client("http://127.0.0.1:1/", None).meta("EX") {
+        Err(CondenserError::Transport(_)) => {}
+        other => panic!("expected Transport, got {:?}", other),
+    }
+}
+
+#[test]
+fn short_urls() {
+    let client = client("http://short.test/", None);
+    assert_eq!(client.short_url("EX").unwrap().as_str(), "http://short.test/EX");
+}
+
+#[test]
+fn retries_server_errors() {
+    let (base, hits) = flaky_meta(2);
+
+    client(&base, None).with_retry(fast_retry(3)).meta("EX").expect("meta");
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn retries_give_up() {
+    let (base, hits) = flaky_meta(usize::max_value());
+
+    match client(&base, None).with_retry(fast_retry(3)).meta("EX") {
+        Err(CondenserError::Status(StatusCode::ServiceUnavailable)) => {}
+        other => panic!("expected Status, got {:?}", other),
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn no_retry_on_client_errors() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&hits);
+    let base = common::serve(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        (StatusCode::NotFound, String::new())
+    });
+
+    assert!(client(&base, None).with_retry(fast_retry(3)).meta("EX").is_err());
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn breaker_trips() {
+    let (base, hits) = flaky_meta(usize::max_value());
+    let trips = Arc::new(AtomicUsize::new(0));
+    let trip_counter = Arc::clone(&trips);
+    let breaker = CircuitBreaker::new(2, Duration::from_secs(60)).on_trip(move |_| {
+        trip_counter.fetch_add(1, Ordering::SeqCst);
+    });
+    let client = client(&base, None)
+        .with_retry(RetryPolicy::none())
+        .with_breaker(Arc::new(breaker));
+
+    assert!(client.meta("EX").is_err());
+    assert!(client.meta("EX").is_err());
+    assert_eq!(trips.load(Ordering::SeqCst), 1);
+
+    // Further requests fail fast without reaching the server.
+    match client.meta("EX") {
+        Err(CondenserError::Unavailable) => {}
+        other => panic!("expected Unavailable, got {:?}", other),
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn breaker_recovers() {
+    let (base, _) = flaky_meta(2);
+    let breaker = Arc::new(CircuitBreaker::new(2, Duration::from_millis(50)));
+    let client = client(&base, None)
+        .with_retry(RetryPolicy::none())
+        .with_breaker(Arc::clone(&breaker));
+
+    assert!(client.meta("EX").is_err());
+    assert!(client.meta("EX").is_err());
+    assert!(breaker.is_open());
+
+    thread::sleep(Duration::from_millis(100));
+    client.meta("EX").expect("meta after cooldown");
+    assert!(!breaker.is_open());
+}
+
+#[test]
+fn no_retry_for_posts() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&hits);
+    let base = common::serve(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        (StatusCode::ServiceUnavailable, String::new())
+    });
+
+    // The server may have stored the code before failing, so shortening again could create a duplicate.
+    match client(&base, Some("KEY")).with_retry(fast_retry(3)).shorten("https://example.com/", None, None) {
+        Err(CondenserError::Status(StatusCode::ServiceUnavailable)) => {}
+        other => panic!("expected Status, got {:?}", other),
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn breaker_reopens() {
+    let (base, hits) = flaky_meta(usize::max_value());
+    let breaker = Arc::new(CircuitBreaker::new(1, Duration::from_millis(50)));
+    let client = client(&base, None)
+        .with_retry(RetryPolicy::none())
+        .with_breaker(Arc::clone(&breaker));
+
+    assert!(client.meta("EX").is_err());
+    thread::sleep(Duration::from_millis(100));
+
+    // The probe fails, so the breaker opens again straight away.
+    assert!(client.meta("EX").is_err());
+    assert!(breaker.is_open());
+    match client.meta("EX") {
+        Err(CondenserError::Unavailable) => {}
+        other => panic!("expected Unavailable, got {:?}", other),
+    }
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn breaker_single_probe() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&hits);
+    let base = common::serve(move |_| {
+        if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+            return (StatusCode::ServiceUnavailable, String::new());
+        }
+        thread::sleep(Duration::from_millis(200));
+        (
+            StatusCode::Ok,
+            r#"{"full_url":"https://example.com/","meta":{"owner":"drakonid","time":"2018-06-01T12:00:00+00:00"}}"#
+                .into(),
+        )
+    });
+    let breaker = Arc::new(CircuitBreaker::new(1, Duration::from_millis(50)));
+    let client = Arc::new(
+        client(&base, None)
+            .with_retry(RetryPolicy::none())
+            .with_breaker(Arc::clone(&breaker)),
+    );
+
+    assert!(client.meta("EX").is_err());
+    thread::sleep(Duration::from_millis(100));
+
+    let probe = {
+        let client = Arc::clone(&client);
+        thread::spawn(move || client.meta("EX"))
+    };
+    thread::sleep(Duration::from_millis(50));
+
+    // Only the probe is let through while it's in flight.
+    match client.meta("EX") {
+        Err(CondenserError::Unavailable) => {}
+        other => panic!("expected Unavailable, got {:?}", other),
+    }
+    probe.join().unwrap().expect("probe");
+    assert!(!breaker.is_open());
+    assert_eq!(hits.load(Ordering::SeqCst), 2);
+}
+
+const GLOBAL: ServerConf<'static> = ServerConf {
+    server: Some("https://global.test/"),
+    key: Some("GLOBAL"),
+};
+
+#[test]
+fn resolve_guild_server() {
+    let guild = ServerConf {
+        server: Some("https://guild.test/"),
+        key: Some("GUILD"),
+    };
+
+    let (server, key) = resolve_server(guild, GLOBAL, true).expect("server");
+    assert_eq!(server.as_str(), "https://guild.test/");
+    assert_eq!(key.as_ref().map(|it| it.as_str()), Some("GUILD"));
+}
+
+#[test]
+fn resolve_guild_server_without_key() {
+    let guild = ServerConf {
+        server: Some("https://guild.test/"),
+        key: None,
+    };
+
+    // The global key must never be sent to a guild's server.
+    let (server, key) = resolve_server(guild, GLOBAL, false).expect("server");
+    assert_eq!(server.as_str(), "https://guild.test/");
+    assert_eq!(key, None);
+    assert!(resolve_server(guild, GLOBAL, true).is_none());
+}
+
+#[test]
+fn resolve_global_server() {
+    let (server, key) = resolve_server(ServerConf::default(), GLOBAL, true).expect("server");
+    assert_eq!(server.as_str(), "https://global.test/");
+    assert_eq!(key.as_ref().map(|it| it.as_str()), Some("GLOBAL"));
+
+    // A key without a server doesn't make a guild use its own server.
+    let guild = ServerConf {
+        server: None,
+        key: Some("GUILD"),
+    };
+    let (_, key) = resolve_server(guild, GLOBAL, true).expect("server");
+    assert_eq!(key.as_ref().map(|it| it.as_str()), Some("GLOBAL"));
+}
+
+#[test]
+fn resolve_unconfigured() {
+    assert!(resolve_server(ServerConf::default(), ServerConf::default(), false).is_none());
+
+    let invalid = ServerConf {
+        server: Some("not a url"),
+        key: None,
+    };
+    assert!(resolve_server(invalid, GLOBAL, false).is_none());
+}
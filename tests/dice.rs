@@ -0,0 +1,142 @@
+extern crate drakonid;
+extern crate rand;
+
+use rand::{SeedableRng, StdRng};
+
+use drakonid::dice::{DiceError, DiceGroup, Expression, Keep, Term, TermResult};
+
+fn rng() -> StdRng {
+    StdRng::from_seed(&[1, 2, 3, 4][..])
+}
+
+#[test]
+fn parse_expressions() {
+    let expr = "4d6kh3 + 2".parse::<Expression>().expect("valid expression");
+    assert_eq!(
+        expr.terms,
+        vec![
+            (
+                false,
+                Term::Dice(DiceGroup {
+                    count: 4,
+                    sides: 6,
+                    explode: false,
+                    keep: Keep::Highest(3),
+                })
+            ),
+            (false, Term::Constant(2)),
+        ]
+    );
+
+    let expr = "d%-1".parse::<Expression>().expect("valid expression");
+    assert_eq!(
+        expr.terms,
+        vec![
+            (
+                false,
+                Term::Dice(DiceGroup {
+                    count: 1,
+                    sides: 100,
+                    explode: false,
+                    keep: Keep::All,
+                })
+            ),
+            (true, Term::Constant(1)),
+        ]
+    );
+
+    let expr = "adv".parse::<Expression>().expect("valid expression");
+    assert_eq!(
+        expr.terms[0].1,
+        Term::Dice(DiceGroup {
+            count: 2,
+            sides: 20,
+            explode: false,
+            keep: Keep::Highest(1),
+        })
+    );
+
+    let expr = "4d6dl1".parse::<Expression>().expect("valid expression");
+    assert_eq!(
+        expr.terms[0].1,
+        Term::Dice(DiceGroup {
+            count: 4,
+            sides: 6,
+            explode: false,
+            keep: Keep::DropLowest(1),
+        })
+    );
+}
+
+#[test]
+fn parse_errors() {
+    assert_eq!("".parse::<Expression>(), Err(DiceError::Empty));
+    assert_eq!("2x6".parse::<Expression>(), Err(DiceError::Unexpected(1)));
+    assert_eq!("101d6".parse::<Expression>(), Err(DiceError::TooManyDice));
+    assert_eq!("60d6+60d6".parse::<Expression>(), Err(DiceError::TooManyDice));
+    assert_eq!("1d0".parse::<Expression>(), Err(DiceError::InvalidSides));
+    assert_eq!("2d6kh3".parse::<Expression>(), Err(DiceError::InvalidKeep));
+    assert_eq!("3d1!".parse::<Expression>(), Err(DiceError::InfiniteExplosion));
+    assert_eq!("99999999".parse::<Expression>(), Err(DiceError::ConstantTooLarge));
+}
+
+#[test]
+fn keep_highest() {
+    let expr = "4d6kh3+1".parse::<Expression>().unwrap();
+    let mut rng = rng();
+    for _ in 0..100 {
+        let result = expr.roll(&mut rng);
+        let dice = match result.terms[0].1 {
+            TermResult::Dice(_, ref dice) => dice.clone(),
+            _ => panic!("expected dice"),
+        };
+
+        assert_eq!(dice.len(), 4);
+        assert_eq!(dice.iter().filter(|it| it.kept).count(), 3);
+        let dropped = dice.iter().find(|it| !it.kept).unwrap();
+        assert!(dice.iter().all(|it| it.value >= dropped.value));
+        assert!(dice.iter().all(|it| it.value >= 1 && it.value <= 6));
+
+        let kept = dice.iter().filter(|it| it.kept).map(|it| i64::from(it.value)).sum::<i64>();
+        assert_eq!(result.total, kept + 1);
+    }
+}
+
+#[test]
+fn exploding_dice() {
+    let expr = "10d2!".parse::<Expression>().unwrap();
+    let mut rng = rng();
+    for _ in 0..100 {
+        let result = expr.roll(&mut rng);
+        let dice = match result.terms[0].1 {
+            TermResult::Dice(_, ref dice) => dice.clone(),
+            _ => panic!("expected dice"),
+        };
+
+        // Every maximum roll adds exactly one extra die.
+        let maxed = dice.iter().filter(|it| it.value == 2).count();
+        assert_eq!(dice.len(), 10 + maxed);
+        assert_eq!(dice.iter().filter(|it| it.exploded).count(), maxed);
+    }
+}
+
+#[test]
+fn exploding_drop_lowest() {
+    let expr = "4d2!dl1".parse::<Expression>().unwrap();
+    let mut rng = rng();
+    let mut saw_explosion = false;
+    for _ in 0..100 {
+        let result = expr.roll(&mut rng);
+        let dice = match result.terms[0].1 {
+            TermResult::Dice(_, ref dice) => dice.clone(),
+            _ => panic!("expected dice"),
+        };
+        saw_explosion |= dice.len() > 4;
+
+        // Only one die is dropped, however many explosions added.
+        assert_eq!(dice.iter().filter(|it| !it.kept).count(), 1);
+        let dropped = dice.iter().find(|it| !it.kept).unwrap();
+        assert!(dice.iter().all(|it| it.value >= dropped.value));
+    }
+    assert!(saw_explosion);
+}
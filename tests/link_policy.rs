@@ -0,0 +1,127 @@
+extern crate drakonid;
+extern crate futures;
+extern crate hyper;
+extern crate url;
+
+mod common;
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use hyper::StatusCode;
+use url::Url;
+
+use drakonid::link_policy::{normalise_domain, LinkPolicy, PolicyViolation};
+
+fn policy() -> LinkPolicy {
+    LinkPolicy {
+        allowed: Vec::new(),
+        blocked: Vec::new(),
+        allow_ip_hosts: false,
+        allow_short_links: false,
+        shorteners: Vec::new(),
+        max_length: 2048,
+    }
+}
+
+fn check(policy: &LinkPolicy, url: &str) -> Result<(), PolicyViolation> {
+    policy.check(&Url::parse(url).unwrap())
+}
+
+#[test]
+fn default_allows_domains() {
+    assert_eq!(check(&policy(), "https://example.com/some/page"), Ok(()));
+}
+
+#[test]
+fn ip_hosts() {
+    let mut policy = policy();
+    assert_eq!(check(&policy, "http://127.0.0.1/"), Err(PolicyViolation::IpHost));
+    assert_eq!(check(&policy, "http://[::1]:8080/"), Err(PolicyViolation::IpHost));
+
+    policy.allow_ip_hosts = true;
+    assert_eq!(check(&policy, "http://127.0.0.1/"), Ok(()));
+}
+
+#[test]
+fn local_hosts() {
+    let policy = policy();
+    assert_eq!(check(&policy, "http://localhost:8080/"), Err(PolicyViolation::LocalHost));
+    assert_eq!(check(&policy, "http://api.localhost/"), Err(PolicyViolation::LocalHost));
+    assert_eq!(check(&policy, "http://intranet/"), Err(PolicyViolation::LocalHost));
+}
+
+#[test]
+fn internal_hosts_not_requested() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&hits);
+    let base = common::serve(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+        (StatusCode::Ok, String::new())
+    });
+
+    let mut policy = policy();
+    policy.allow_ip_hosts = true;
+    assert_eq!(policy.check_redirects(&Url::parse(&base).unwrap()), Ok(()));
+    assert_eq!(hits.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn redirect_targets_not_shown() {
+    let violation = PolicyViolation::Redirect {
+        to: Url::parse("http://10.0.0.1/admin").unwrap(),
+        violation: Box::new(PolicyViolation::IpHost),
+    };
+    assert!(!violation.to_string().contains("10.0.0.1"));
+}
+
+#[test]
+fn blocked_domains() {
+    let mut policy = policy();
+    policy.blocked.push("example.com".into());
+
+    assert_eq!(check(&policy, "https://example.com/"), Err(PolicyViolation::Blocked("example.com".into())));
+    assert_eq!(check(&policy, "https://WWW.Example.com./"), Err(PolicyViolation::Blocked("www.example.com".into())));
+    assert_eq!(check(&policy, "https://notexample.com/"), Ok(()));
+}
+
+#[test]
+fn allowed_domains() {
+    let mut policy = policy();
+    policy.allowed.push("example.com".into());
+
+    assert_eq!(check(&policy, "https://cdn.example.com/"), Ok(()));
+    assert_eq!(check(&policy, "https://example.org/"), Err(PolicyViolation::NotAllowed("example.org".into())));
+}
+
+#[test]
+fn short_links() {
+    let mut policy = policy();
+    policy.shorteners.push("short.test".into());
+
+    assert_eq!(check(&policy, "https://bit.ly/abc"), Err(PolicyViolation::ShortLink("bit.ly".into())));
+    assert_eq!(check(&policy, "http://short.test/EX"), Err(PolicyViolation::ShortLink("short.test".into())));
+
+    policy.allow_short_links = true;
+    assert_eq!(check(&policy, "https://bit.ly/abc"), Ok(()));
+}
+
+#[test]
+fn max_length() {
+    let mut policy = policy();
+    policy.max_length = 24;
+
+    assert_eq!(check(&policy, "https://example.com/"), Ok(()));
+    assert_eq!(
+        check(&policy, "https://example.com/long/path"),
+        Err(PolicyViolation::TooLong { length: 29, max: 24 })
+    );
+}
+
+#[test]
+fn normalising_domains() {
+    assert_eq!(normalise_domain("*.Example.COM."), Some("example.com".into()));
+    assert_eq!(normalise_domain("localhost"), None);
+    assert_eq!(normalise_domain("10.0.0.1"), None);
+    assert_eq!(normalise_domain("not a domain"), None);
+}
@@ -0,0 +1,68 @@
+extern crate chrono;
+extern crate drakonid;
+extern crate serenity;
+
+use chrono::{Duration, TimeZone, Utc};
+use serenity::model::id::{MessageId, UserId};
+
+use drakonid::commands::moderation::{bulk_deletable, PurgeFilter};
+
+fn parse(input: &str) -> PurgeFilter {
+    PurgeFilter::parse(input).unwrap_or_else(|err| panic!("{:?} should parse, got {}", input, err))
+}
+
+#[test]
+fn count_only() {
+    let filter = parse("50");
+    assert_eq!(filter.count, 50);
+    assert_eq!(filter.user, None);
+    assert!(filter.pattern.is_none());
+    assert!(!filter.bots_only);
+    assert!(!filter.attachments_only);
+}
+
+#[test]
+fn combined_filters() {
+    let filter = parse("20 --user <@!1234> --regex ^spam --bots --after 99");
+    assert_eq!(filter.count, 20);
+    assert_eq!(filter.user, Some(UserId(1234)));
+    assert_eq!(filter.pattern.as_ref().map(|it| it.as_str()), Some("^spam"));
+    assert!(filter.bots_only);
+    assert_eq!(filter.after, Some(MessageId(99)));
+    assert_eq!(filter.before, None);
+
+    let filter = parse("5 --attachments --user 42 --before 7");
+    assert_eq!(filter.user, Some(UserId(42)));
+    assert!(filter.attachments_only);
+    assert_eq!(filter.before, Some(MessageId(7)));
+}
+
+#[test]
+fn invalid_counts() {
+    assert!(PurgeFilter::parse("").is_err());
+    assert!(PurgeFilter::parse("--bots").is_err());
+    assert!(PurgeFilter::parse("0").is_err());
+    assert!(PurgeFilter::parse("1001").is_err());
+    assert!(PurgeFilter::parse("-5").is_err());
+}
+
+#[test]
+fn invalid_options() {
+    assert!(PurgeFilter::parse("10 --user").is_err());
+    assert!(PurgeFilter::parse("10 --user someone").is_err());
+    assert!(PurgeFilter::parse("10 --regex").is_err());
+    assert!(PurgeFilter::parse("10 --regex (unclosed").is_err());
+    assert!(PurgeFilter::parse("10 --before yesterday").is_err());
+    assert!(PurgeFilter::parse("10 --everything").is_err());
+}
+
+#[test]
+fn bulk_delete_cutoff() {
+    let now = Utc.ymd(2018, 6, 15).and_hms(12, 0, 0);
+    assert!(bulk_deletable(now - Duration::days(1), now));
+    assert!(bulk_deletable(now - Duration::days(13), now));
+    // Discord refuses to bulk delete messages from 14 days ago or earlier, with a margin for clock skew.
+    assert!(!bulk_deletable(now - Duration::days(14), now));
+    assert!(!bulk_deletable(now - Duration::days(14) + Duration::minutes(1), now));
+    assert!(!bulk_deletable(now - Duration::days(30), now));
+}
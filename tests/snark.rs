@@ -0,0 +1,67 @@
+extern crate drakonid;
+extern crate serenity;
+
+use std::time::{Duration, Instant};
+
+use serenity::model::id::{GuildId, UserId};
+
+use drakonid::snark::SnarkEngine;
+
+const RULES: &str = r#"{
+    "cooldown_seconds": 60,
+    "triggers": [
+        { "pattern": "^no u$", "mention_only": true, "responses": ["no u"] },
+        { "pattern": "bad bot", "responses": ["sorry"] }
+    ],
+    "failed_commands": { "threshold": 3, "window_seconds": 30, "responses": ["try !help"] }
+}"#;
+
+const GUILD: GuildId = GuildId(1);
+const OTHER_GUILD: GuildId = GuildId(2);
+const USER: UserId = UserId(10);
+
+#[test]
+fn triggers() {
+    let engine = SnarkEngine::from_json(RULES).expect("valid rules");
+    let now = Instant::now();
+
+    assert_eq!(engine.message(GUILD, "no u", false, now), None);
+    assert_eq!(engine.message(GUILD, "<@1234> NO U", true, now), Some("no u".into()));
+    assert_eq!(engine.message(OTHER_GUILD, "what a bad bot", false, now), Some("sorry".into()));
+    assert_eq!(engine.message(OTHER_GUILD, "good bot", false, now), None);
+}
+
+#[test]
+fn cooldown() {
+    let engine = SnarkEngine::from_json(RULES).expect("valid rules");
+    let now = Instant::now();
+
+    assert!(engine.message(GUILD, "bad bot", false, now).is_some());
+    assert!(engine.message(GUILD, "bad bot", false, now + Duration::from_secs(30)).is_none());
+    assert!(engine.message(OTHER_GUILD, "bad bot", false, now + Duration::from_secs(30)).is_some());
+    assert!(engine.message(GUILD, "bad bot", false, now + Duration::from_secs(61)).is_some());
+}
+
+#[test]
+fn failed_commands() {
+    let engine = SnarkEngine::from_json(RULES).expect("valid rules");
+    let now = Instant::now();
+
+    assert_eq!(engine.command_failed(GUILD, USER, now), None);
+    assert_eq!(engine.command_failed(GUILD, USER, now + Duration::from_secs(1)), None);
+    assert_eq!(engine.command_failed(GUILD, USER, now + Duration::from_secs(2)), Some("try !help".into()));
+
+    // Failures outside the window don't count, and a success resets the count.
+    let later = now + Duration::from_secs(120);
+    assert_eq!(engine.command_failed(GUILD, USER, later), None);
+    assert_eq!(engine.command_failed(GUILD, USER, later + Duration::from_secs(40)), None);
+    assert_eq!(engine.command_failed(GUILD, USER, later + Duration::from_secs(41)), None);
+    engine.command_succeeded(GUILD, USER);
+    assert_eq!(engine.command_failed(GUILD, USER, later + Duration::from_secs(42)), None);
+}
+
+#[test]
+fn bad_pattern() {
+    let rules = r#"{ "triggers": [ { "pattern": "(", "responses": ["x"] } ] }"#;
+    assert!(SnarkEngine::from_json(rules).is_err());
+}
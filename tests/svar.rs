@@ -0,0 +1,53 @@
+extern crate drakonid;
+#[macro_use]
+extern crate serde_json;
+extern crate serenity;
+
+use std::env;
+use std::process;
+
+use serenity::builder::CreateEmbed;
+use serenity::framework::standard::Command;
+use serenity::model::id::GuildId;
+use serenity::utils::vecmap_to_json_map;
+
+use drakonid::commands::svar::SVarSet;
+use drakonid::server::config::{ConfigError, ConfigStore};
+use drakonid::server::svar::{self, SVarRollDetail};
+use drakonid::utils::usage_error;
+
+/// Opens a config store in a fresh directory, so tests don't share state.
+fn store(name: &str) -> ConfigStore {
+    let root = env::temp_dir().join(format!("drakonid-test-{}-{}", name, process::id()));
+    ConfigStore::new(root).expect("config store")
+}
+
+#[test]
+fn one_of_accepts_choices() {
+    let info = svar::find("roll_detail").expect("roll_detail");
+    assert!(info.validate(&json!("full"), |_| json!(null)).is_ok());
+    assert!(info.validate(&json!("summary"), |_| json!(null)).is_ok());
+    assert!(info.validate(&json!("verbose"), |_| json!(null)).is_err());
+    assert!(info.validate(&json!(1), |_| json!(null)).is_err());
+}
+
+#[test]
+fn rejected_value_shows_usage() {
+    let store = store("svar-rejected");
+    let guild = GuildId(1);
+
+    let reason = match store.set_raw(guild, "roll_detail", json!("verbose")) {
+        Err(ConfigError::Invalid(reason)) => reason,
+        other => panic!("expected Invalid, got {:?}", other),
+    };
+    assert_eq!(reason, "Value must be one of: `full`, `summary`.");
+    assert_eq!(store.get::<SVarRollDetail>(guild), "full");
+
+    // This is the embed `!svar set` sends for a rejected value.
+    let opts = SVarSet::new().options();
+    let embed = vecmap_to_json_map(usage_error(CreateEmbed::default(), "svar set", &reason, &opts).0);
+    assert_eq!(embed["title"], json!("Error"));
+    assert_eq!(embed["description"], json!(reason));
+    assert_eq!(embed["fields"][0]["name"], json!("Usage"));
+    assert_eq!(embed["fields"][0]["value"], json!("`!svar set KEY VALUE`"));
+}
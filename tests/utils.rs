@@ -0,0 +1,61 @@
+extern crate chrono;
+extern crate drakonid;
+
+use chrono::Duration;
+
+use drakonid::utils::{format_duration, parse_duration, qr_png};
+
+/// Reads a big-endian `u32` from the PNG header.
+fn header_u32(png: &[u8], offset: usize) -> u32 {
+    png[offset..offset + 4].iter().fold(0, |acc, &it| acc << 8 | u32::from(it))
+}
+
+#[test]
+fn qr_png_renders() {
+    let png = qr_png("https://short.test/EX").expect("qr code");
+
+    assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    // The IHDR chunk comes first, holding the width and height.
+    assert_eq!(&png[12..16], b"IHDR");
+    assert!(header_u32(&png, 16) >= 256);
+    assert!(header_u32(&png, 20) >= 256);
+}
+
+#[test]
+fn qr_png_too_long() {
+    // Larger than the biggest QR code can hold.
+    let data = "a".repeat(8000);
+    assert!(qr_png(&data).is_none());
+}
+
+#[test]
+fn parse_durations() {
+    assert_eq!(parse_duration("30m"), Some(Duration::minutes(30)));
+    assert_eq!(parse_duration("2w"), Some(Duration::weeks(2)));
+    assert_eq!(parse_duration("1d12h"), Some(Duration::hours(36)));
+    assert_eq!(parse_duration("1h30m15s"), Some(Duration::seconds(5415)));
+}
+
+#[test]
+fn parse_invalid_durations() {
+    assert_eq!(parse_duration(""), None);
+    assert_eq!(parse_duration("m"), None);
+    assert_eq!(parse_duration("1d h"), None);
+    assert_eq!(parse_duration("30"), None);
+    assert_eq!(parse_duration("5y"), None);
+    assert_eq!(parse_duration("0m"), None);
+}
+
+#[test]
+fn parse_overflowing_durations() {
+    assert_eq!(parse_duration("99999999999999999999d"), None);
+    assert_eq!(parse_duration("10001w"), None);
+}
+
+#[test]
+fn format_durations() {
+    assert_eq!(format_duration(Duration::zero()), "0s");
+    assert_eq!(format_duration(Duration::seconds(90)), "1m 30s");
+    assert_eq!(format_duration(Duration::hours(36)), "1d 12h");
+    assert_eq!(format_duration(Duration::weeks(2)), "14d");
+}